@@ -0,0 +1,34 @@
+//! Compares the speed of a program that writes to a lot of memory beyond the
+//! end of its tape, with and without `ProgramBuilder::memory_hint`.
+//!
+//! Run it with `cargo bench --bench memory_hint`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use intcode::Program;
+
+const START: usize = 1000;
+const COUNT: i64 = 100_000;
+
+fn run(hint: bool) {
+    // Writes 1 to every address from 1000 on, through the relative base,
+    // counting address 17 down to 0 as it goes.
+    let tape = vec![109, START as i64, 21101, 1, 0, 0, 109, 1, 1001, 17, -1, 17, 1005, 17, 2, 99, 0, COUNT];
+    let mut builder = Program::builder(tape);
+    if hint {
+        builder = builder.memory_hint(START + COUNT as usize);
+    }
+    let mut program = builder.build();
+    program.run_till_halted_or_blocked().unwrap();
+    assert!(program.halted());
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory_hint");
+    group.bench_function("without_hint", |b| b.iter(|| run(false)));
+    group.bench_function("with_hint", |b| b.iter(|| run(true)));
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
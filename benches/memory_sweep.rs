@@ -0,0 +1,53 @@
+//! Times a program that sweeps a million addresses beyond the end of its
+//! tape, first writing to every one of them and then reading them all back.
+//!
+//! Run it with `cargo bench --bench memory_sweep`. The numbers this is
+//! compared against, measured with the same bench before and after memory
+//! beyond the tape moved out of a `BTreeMap`, are in `benches/README.md`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use intcode::{Number, Program};
+
+const START: Number = 1000;
+const COUNT: Number = 1_000_000;
+
+fn program() -> Program {
+    // Writes 0 up to COUNT to the addresses from START on, through the
+    // relative base, then adds them all up. The counters live at 100 to 103,
+    // also beyond the tape.
+    Program::new(vec![
+        109, START, 21001, 100, 0, 0, 109, 1, 1001, 100, 1, 100, 1007, 100, COUNT, 101, 1005, 101, 2,
+        109, -COUNT, 2001, 102, 0, 102, 109, 1, 1001, 103, 1, 103, 1007, 103, COUNT, 101, 1005, 101, 21,
+        4, 102, 99,
+    ])
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory_sweep");
+    group.sample_size(10);
+    group.bench_function("run_till_halted_or_blocked", |b| {
+        b.iter_batched(
+            program,
+            |mut p| {
+                p.run_till_halted_or_blocked().unwrap();
+                assert_eq!(p.get_output(), Some(COUNT * (COUNT - 1) / 2));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("run_fast", |b| {
+        b.iter_batched(
+            program,
+            |mut p| {
+                p.run_fast().unwrap();
+                assert_eq!(p.get_output(), Some(COUNT * (COUNT - 1) / 2));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
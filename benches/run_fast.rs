@@ -0,0 +1,49 @@
+//! Compares the speed of `run_fast` with that of the general run loop.
+//!
+//! The program counts down from a large number using the relative base, so
+//! every instruction has to decode its parameter modes.
+//!
+//! Run it with `cargo bench --bench run_fast`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use intcode::Program;
+
+const COUNT: i64 = 100_000;
+
+fn program() -> Program {
+    // Counts address 100 down to 0 through the relative base, then outputs
+    // the number of steps taken, which is kept at address 101.
+    let mut tape = vec![109, 100, 21201, 0, -1, 0, 22101, 1, 1, 1, 1205, 0, 2, 4, 101, 99];
+    tape.resize(102, 0);
+    tape[100] = COUNT;
+    Program::new(tape)
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_down");
+    group.bench_function("run_till_halted_or_blocked", |b| {
+        b.iter_batched(
+            program,
+            |mut p| {
+                p.run_till_halted_or_blocked().unwrap();
+                assert_eq!(p.get_output(), Some(COUNT));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("run_fast", |b| {
+        b.iter_batched(
+            program,
+            |mut p| {
+                p.run_fast().unwrap();
+                assert_eq!(p.get_output(), Some(COUNT));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
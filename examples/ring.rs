@@ -0,0 +1,143 @@
+//! Runs a network of 50 programs like the one in day 23, each on its own
+//! thread, with a NAT on another thread at address 255.
+//!
+//! The machines are connected in a ring: each one adds 1 to the x of every
+//! packet it gets and passes it on to the next address, and the last one sends
+//! it to the NAT. Machine 0 starts things off with a single packet. Once the
+//! packet ends up at the NAT, the whole network is idle, so the NAT sends it
+//! back to machine 0, and the rest goes around again. This stops when the NAT
+//! sends the same y twice in a row.
+//!
+//! All packets go through the NAT thread, which passes on the ones for other
+//! machines. A machine counts as idle when it has read -1 without sending
+//! anything, the same way `Network::is_idle` decides it for a whole network.
+//! Since a packet can still be on its way to a machine that said it was idle,
+//! each machine also says how many packets it has read, and it only counts as
+//! idle if that is every packet it was sent.
+
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use intcode::{Number, Packet, Program, NAT_ADDRESS};
+
+const MACHINES: usize = 50;
+
+/// What a machine tells the NAT.
+enum Message {
+    Packet(Packet),
+    /// The machine read -1 and sent nothing, after reading `received` packets
+    /// in total.
+    Idle { addr: usize, received: usize },
+}
+
+fn main() {
+    let last = MACHINES as Number - 1;
+    let tape = vec![
+        // Read the address into 200, and work out where packets go in 202.
+        3, 200, 1007, 200, last, 201, 1006, 201, 16, 1001, 200, 1, 202, 1105, 1, 20, 1101, NAT_ADDRESS, 0,
+        202,
+        // Machine 0 sends (1, 1, 7) to start with.
+        1005, 200, 29, 104, 1, 104, 1, 104, 7,
+        // Read packets into 203 and 204 forever, skipping -1, and pass each
+        // one on with x increased by 1.
+        3, 203, 1008, 203, -1, 205, 1005, 205, 29, 3, 204, 1001, 203, 1, 203, 4, 202, 4, 203, 4, 204, 1105,
+        1, 29,
+    ];
+
+    let (to_nat, nat_inbox) = mpsc::channel();
+    let mut inboxes = Vec::new();
+    let mut handles = Vec::new();
+    for addr in 0..MACHINES {
+        let (tx, inbox) = mpsc::channel();
+        inboxes.push(tx);
+        let tape = tape.clone();
+        let to_nat = to_nat.clone();
+        handles.push(thread::spawn(move || machine(addr, tape, inbox, to_nat)));
+    }
+    drop(to_nat);
+
+    let y = thread::spawn(move || nat(nat_inbox, inboxes)).join().unwrap();
+    println!("the NAT sent y = {} twice in a row", y);
+
+    // The NAT has dropped the machines' inboxes by now, which stops them.
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+/// Runs the machine at `addr`, reading packets from `inbox` and sending
+/// everything it outputs to the NAT, until either channel is disconnected.
+fn machine(addr: usize, tape: Vec<Number>, inbox: Receiver<(Number, Number)>, to_nat: Sender<Message>) {
+    let mut program = Program::new(tape);
+    program.push_input(addr as Number);
+    let mut received = 0;
+    let mut starved = false;
+    loop {
+        program.run_till_halted_or_blocked().unwrap();
+        let outputs = program.drain_output_chunks(3);
+        let sent = !outputs.is_empty();
+        for record in outputs {
+            let packet = Packet { from: addr as Number, to: record[0], x: record[1], y: record[2] };
+            if to_nat.send(Message::Packet(packet)).is_err() {
+                return;
+            }
+        }
+        if program.halted() {
+            return;
+        }
+        if starved && !sent && to_nat.send(Message::Idle { addr, received }).is_err() {
+            return;
+        }
+        match inbox.recv_timeout(Duration::from_millis(1)) {
+            Ok((x, y)) => {
+                received += 1;
+                starved = false;
+                program.push_input(x);
+                program.push_input(y);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                starved = true;
+                program.push_input(-1);
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// Passes packets on to the machines, and whenever they are all idle, sends
+/// the last packet it got itself to machine 0. Returns the y it sent twice in
+/// a row.
+fn nat(inbox: Receiver<Message>, machines: Vec<Sender<(Number, Number)>>) -> Number {
+    let mut forwarded = vec![0; machines.len()];
+    let mut idle = vec![false; machines.len()];
+    let mut kept = None;
+    let mut last_y = None;
+    for message in inbox {
+        match message {
+            Message::Packet(packet) => {
+                idle[packet.from as usize] = false;
+                if packet.to == NAT_ADDRESS {
+                    kept = Some(packet);
+                } else if let Some(machine) = machines.get(packet.to as usize) {
+                    machine.send((packet.x, packet.y)).unwrap();
+                    forwarded[packet.to as usize] += 1;
+                    idle[packet.to as usize] = false;
+                }
+            }
+            Message::Idle { addr, received } => idle[addr] = received == forwarded[addr],
+        }
+
+        if let (true, Some(packet)) = (idle.iter().all(|&i| i), kept) {
+            println!("the network is idle, so the NAT sends x = {}, y = {} to 0", packet.x, packet.y);
+            if last_y == Some(packet.y) {
+                return packet.y;
+            }
+            last_y = Some(packet.y);
+            machines[0].send((packet.x, packet.y)).unwrap();
+            forwarded[0] += 1;
+            idle[0] = false;
+        }
+    }
+    panic!("all machines stopped");
+}
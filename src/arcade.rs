@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use crate::{Number, Program};
+
+/// Consumes the program's unconsumed outputs three at a time, as records of
+/// the form `(x, y, value)` drawn by the arcade cabinet in day 13, and puts
+/// the tiles among them on `tiles`. The score record, which has an x of -1
+/// and a y of 0, is returned if there is one, and never put on `tiles`. If
+/// fewer than three outputs are left at the end, they stay in the queue.
+fn draw_tiles(program: &mut Program, tiles: &mut HashMap<(i64, i64), Number>) -> Option<Number> {
+    let mut score = None;
+    for record in program.drain_output_chunks(3) {
+        let (x, y, value) = (record[0], record[1], record[2]);
+        if (x, y) == (-1, 0) {
+            score = Some(value);
+        } else {
+            tiles.insert((x, y), value);
+        }
+    }
+    score
+}
+
+/// Runs an arcade cabinet program from day 13 until it halts or needs input,
+/// and returns how many tiles on the screen have the given tile id.
+///
+/// The program's output is read as records of the form `(x, y, tile_id)`. A
+/// tile that is drawn more than once counts with the id it was drawn with
+/// last, and the score record, `(-1, 0, score)`, is not a tile.
+///
+/// # Example
+/// ```
+/// // Draws a block at (1, 0) and a wall at (2, 0), then covers the wall with
+/// // a second block, and sets the score to 2.
+/// let mut program = intcode::Program::new(vec![
+///     104, 1, 104, 0, 104, 2, 104, 2, 104, 0, 104, 1, 104, 2, 104, 0, 104, 2,
+///     104, -1, 104, 0, 104, 2, 99,
+/// ]);
+/// assert_eq!(intcode::count_tiles(&mut program, 2), 2);
+/// ```
+pub fn count_tiles(program: &mut Program, tile_id: Number) -> usize {
+    program.run_without_pausing();
+    let mut tiles = HashMap::new();
+    draw_tiles(program, &mut tiles);
+    tiles.values().filter(|&&id| id == tile_id).count()
+}
+
+/// Runs an arcade cabinet program from day 13, keeping track of the screen
+/// and the score as the program draws them.
+///
+/// # Example
+/// ```
+/// use intcode::{Arcade, Program};
+///
+/// // Draws a tile, sets the score to 10, draws another tile over the first
+/// // one, and waits for the joystick. Then it sets the score to 20.
+/// let mut arcade = Arcade::new(Program::new(vec![
+///     104, 3, 104, 4, 104, 1, 104, -1, 104, 0, 104, 10, 104, 3, 104, 4, 104, 2,
+///     3, 100, 104, -1, 104, 0, 104, 20, 99,
+/// ]));
+/// assert_eq!(arcade.score(), None);
+///
+/// arcade.run();
+/// assert_eq!(arcade.score(), Some(10));
+/// assert_eq!(arcade.tiles()[&(3, 4)], 2);
+///
+/// arcade.move_joystick(0);
+/// arcade.run();
+/// assert_eq!(arcade.score(), Some(20));
+/// assert!(arcade.program().halted());
+/// ```
+pub struct Arcade {
+    program: Program,
+    tiles: HashMap<(i64, i64), Number>,
+    score: Option<Number>,
+}
+
+impl Arcade {
+    /// Creates an arcade cabinet that runs the given program.
+    pub fn new(program: Program) -> Arcade {
+        Arcade {
+            program,
+            tiles: HashMap::new(),
+            score: None,
+        }
+    }
+
+    /// Runs the program until it halts or waits for the joystick, and draws
+    /// everything it output on the screen.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `Program::run_or_panic`.
+    pub fn run(&mut self) {
+        self.program.run_without_pausing();
+        if let Some(score) = draw_tiles(&mut self.program, &mut self.tiles) {
+            self.score = Some(score);
+        }
+    }
+
+    /// Gives the joystick position to the program: -1 for left, 0 for neutral
+    /// and 1 for right.
+    pub fn move_joystick(&mut self, position: Number) {
+        self.program.push_input(position);
+    }
+
+    /// Returns the most recent score the program drew, wherever it was among
+    /// the tiles, or `None` if it hasn't drawn a score yet.
+    pub fn score(&self) -> Option<Number> {
+        self.score
+    }
+
+    /// Returns the tile id of every position on the screen that was drawn.
+    pub fn tiles(&self) -> &HashMap<(i64, i64), Number> {
+        &self.tiles
+    }
+
+    /// Returns the program running on the cabinet.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{Number, Program};
+
+/// What a program printed during `Program::run_ascii`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsciiOutput {
+    /// All outputs in the ASCII range, as text.
+    pub text: String,
+    /// The last output outside of the ASCII range, which is where programs
+    /// that otherwise talk in ASCII usually put their answer.
+    pub answer: Option<Number>,
+}
+
+impl Program {
+    /// Consumes all unconsumed outputs and returns them as bytes.
+    ///
+    /// Each output is truncated to its lowest 8 bits, the same way `as u8`
+    /// does, so values outside of `0..=255` don't cause an error but come out
+    /// as some other byte. Use `get_output` when the program may produce such
+    /// values and they matter.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 72, 104, 105, 104, 10, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.output_as_bytes(), b"Hi\n");
+    /// assert_eq!(program.get_output(), None);
+    /// ```
+    pub fn output_as_bytes(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while let Some(i) = self.get_output() {
+            bytes.push(i as u8);
+        }
+        bytes
+    }
+
+    /// Consumes all unconsumed outputs and returns the ones in the ASCII range,
+    /// `0..=127`, as text. Other outputs are consumed too, but left out of the
+    /// text; `last_non_ascii_output` finds the last of them, which is usually
+    /// the answer.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 79, 104, 75, 104, 10, 104, 1000, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.drain_output_ascii(), "OK\n");
+    /// assert_eq!(program.get_output(), None);
+    /// assert_eq!(program.last_non_ascii_output(), Some(1000));
+    /// ```
+    pub fn drain_output_ascii(&mut self) -> String {
+        let mut text = String::new();
+        while let Some(i) = self.get_output() {
+            if let 0..=127 = i {
+                text.push(i as u8 as char);
+            }
+        }
+        text
+    }
+
+    /// Returns the last output outside of the ASCII range, `0..=127`, whether
+    /// it has been consumed or not. Programs that otherwise talk in ASCII use
+    /// such an output for their answer, as in days 17 and 25.
+    pub fn last_non_ascii_output(&self) -> Option<Number> {
+        self.output.iter().rev().copied().find(|i| !(0..=127).contains(i))
+    }
+
+    /// Adds a string to the program's input queue as ASCII, one byte at a
+    /// time. Like `push_input`, this makes a program that is waiting for input
+    /// runnable again, unless the string is empty.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 100, 99]);
+    /// program.push_input_ascii("NOT");
+    /// assert_eq!(program.pending_input(), &[78, 79, 84]);
+    /// ```
+    pub fn push_input_ascii(&mut self, s: &str) {
+        for b in s.bytes() {
+            self.push_input(Number::from(b));
+        }
+    }
+
+    /// Adds a line to the program's input queue as ASCII, followed by a
+    /// newline, the way programs such as the ones in days 21 and 25 read
+    /// their commands.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 100, 99]);
+    /// program.push_input_line("WALK");
+    /// assert_eq!(program.pending_input(), &[87, 65, 76, 75, 10]);
+    /// ```
+    pub fn push_input_line(&mut self, line: &str) {
+        self.push_input_ascii(line);
+        self.push_input(10);
+    }
+
+    /// Sends lines of ASCII input to the program, runs it until it halts or
+    /// blocks, and consumes its output.
+    ///
+    /// Each line is sent followed by a newline. Outputs in `0..=127` are
+    /// collected as text, which is mostly prompts and drawings, while any
+    /// other output is taken to be an answer, since those can't be ASCII.
+    ///
+    /// # Example
+    /// ```
+    /// // Prints "?\n", reads a character, then prints that character plus 1000.
+    /// let mut program = intcode::Program::new(vec![
+    ///     104, 63, 104, 10, 3, 100, 1001, 100, 1000, 100, 4, 100, 99,
+    /// ]);
+    /// let output = program.run_ascii(&["A"]);
+    /// assert_eq!(output.text, "?\n");
+    /// assert_eq!(output.answer, Some(1065));
+    /// ```
+    pub fn run_ascii(&mut self, lines: &[&str]) -> AsciiOutput {
+        for line in lines {
+            self.push_input_line(line);
+        }
+        self.run_without_pausing();
+
+        let mut output = AsciiOutput { text: String::new(), answer: None };
+        while let Some(i) = self.get_output() {
+            match i {
+                0..=127 => output.text.push(i as u8 as char),
+                _ => output.answer = Some(i),
+            }
+        }
+        output
+    }
+
+    /// Consumes ASCII output that draws a grid, such as a map, and returns it
+    /// as rows of characters.
+    ///
+    /// Rows are separated by newlines, and newlines before the first row are
+    /// skipped. After that, an empty line ends the grid: outputs up to and
+    /// including it are consumed, and anything after it is left in the output
+    /// queue. Without an empty line, all outputs are consumed, and a single
+    /// newline at the end is allowed. Like `drain_output_ascii`, outputs
+    /// outside the ASCII range are consumed but left out of the grid, so that
+    /// `last_non_ascii_output` can still find an answer that follows it.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![
+    ///     104, 35, 104, 46, 104, 10,
+    ///     104, 46, 104, 35, 104, 10,
+    ///     104, 10,
+    ///     104, 63, 99,
+    /// ]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.output_as_grid(), vec![vec!['#', '.'], vec!['.', '#']]);
+    /// assert_eq!(program.get_output(), Some(63));
+    /// ```
+    pub fn output_as_grid(&mut self) -> Vec<Vec<char>> {
+        let mut grid = Vec::new();
+        let mut row = Vec::new();
+
+        while let Some(i) = self.get_output() {
+            if i == 10 {
+                if row.is_empty() {
+                    if grid.is_empty() {
+                        continue;
+                    }
+                    break;
+                }
+                grid.push(std::mem::take(&mut row));
+            } else if let 0..=127 = i {
+                row.push(i as u8 as char);
+            }
+        }
+        if !row.is_empty() {
+            grid.push(row);
+        }
+        grid
+    }
+}
+
+#[test]
+fn test_output_as_grid_skips_the_answer() {
+    let mut program = Program::new(vec![104, 35, 104, 46, 104, 10, 104, 200, 104, 12345, 99]);
+    program.run_till_halted_or_blocked().unwrap();
+    assert_eq!(program.output_as_grid(), vec![vec!['#', '.']]);
+    assert_eq!(program.get_output(), None);
+    assert_eq!(program.last_non_ascii_output(), Some(12345));
+}
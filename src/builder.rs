@@ -0,0 +1,109 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::{Number, Program};
+
+/// Creates a `Program` with settings that `Program::new` doesn't take. Get one
+/// from `Program::builder`.
+///
+/// # Example
+/// ```
+/// let mut program = intcode::Program::builder(vec![1101, 2, 3, 1000, 4, 1000, 99])
+///     .memory_hint(1001)
+///     .build();
+/// program.run_till_halted_or_blocked().unwrap();
+/// assert_eq!(program.get_output(), Some(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProgramBuilder {
+    tape: Vec<Number>,
+    memory_hint: usize,
+    fills: Vec<(Number, Range<usize>)>,
+}
+
+impl ProgramBuilder {
+    /// Sets aside memory for addresses up to, but not including, `size` when
+    /// the program is built, so that the program can use them without memory
+    /// having to grow.
+    ///
+    /// This only makes a difference to how fast the program runs. Memory
+    /// beyond the end of the tape still reads as 0, counts as never written
+    /// until it is, and isn't included in dumps or patches unless it holds a
+    /// value. A size no larger than the tape does nothing.
+    pub fn memory_hint(mut self, size: usize) -> ProgramBuilder {
+        self.memory_hint = size;
+        self
+    }
+
+    /// Fills the memory in `range` with `value` instead of 0, for addresses
+    /// beyond the end of the tape. Addresses that are part of the tape keep
+    /// the tape's values. The cells that are filled count as written, so
+    /// they can be read with strict reads turned on.
+    ///
+    /// This can be called more than once, and when ranges overlap, the one
+    /// given last wins. `reset` clears the filled memory along with the rest
+    /// of the memory beyond the tape.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::builder(vec![4, 100, 4, 3, 99])
+    ///     .fill_extra(-1, 50..150)
+    ///     .fill_extra(7, 0..10)
+    ///     .build();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(-1));
+    /// assert_eq!(program.get_output(), Some(3));
+    /// ```
+    pub fn fill_extra(mut self, value: Number, range: Range<usize>) -> ProgramBuilder {
+        self.fills.push((value, range));
+        self
+    }
+
+    /// Creates the program.
+    pub fn build(self) -> Program {
+        let start = self.tape.len();
+        let mut program = Program::new(self.tape);
+        if self.memory_hint > start {
+            Arc::make_mut(&mut program.extra).grow(self.memory_hint - start);
+        }
+        for (value, range) in self.fills {
+            for pos in range.start.max(start)..range.end {
+                program.set_extra(pos, value);
+            }
+        }
+        program
+    }
+}
+
+impl Program {
+    /// Starts building a program from the given tape. See `ProgramBuilder`.
+    pub fn builder(tape: Vec<Number>) -> ProgramBuilder {
+        ProgramBuilder {
+            tape,
+            memory_hint: 0,
+            fills: Vec::new(),
+        }
+    }
+}
+
+#[test]
+fn test_memory_hint_is_not_observable() {
+    // Writes two values beyond the tape, at 50 and 200, with a 0 in between
+    // at 120, and outputs all three.
+    let tape = vec![1101, 3, 4, 50, 1101, 0, 0, 120, 1101, 7, 0, 200, 4, 50, 4, 120, 4, 200, 99];
+    let mut plain = Program::new(tape.clone());
+    let mut hinted = Program::builder(tape).memory_hint(150).build();
+    plain.run_till_halted_or_blocked().unwrap();
+    hinted.run_till_halted_or_blocked().unwrap();
+
+    assert_eq!(hinted.output, plain.output);
+    assert_eq!(hinted.effective_memory(), plain.effective_memory());
+    assert_eq!(hinted.minimal_patch(), plain.minimal_patch());
+    assert_eq!(hinted.to_json(), plain.to_json());
+    hinted.compact_memory();
+    plain.compact_memory();
+    assert_eq!(hinted.to_json(), plain.to_json());
+    hinted.strict_reads(true);
+    assert_eq!(hinted.read(120), Err(crate::RunError::UninitializedRead(120)));
+    assert_eq!(hinted.read(50), Ok(7));
+}
@@ -0,0 +1,56 @@
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::{Number, Program};
+
+impl Program {
+    /// Runs the program with its input and output connected to channels, until
+    /// it halts or one of the channels is disconnected.
+    ///
+    /// Whenever the program needs input, this blocks until a value arrives on
+    /// `input`. Every output is sent on `output` as soon as the program blocks
+    /// or halts. If `input`'s sender is dropped while the program is waiting
+    /// for input, or `output`'s receiver is dropped while there is output to
+    /// send, this returns without the program having halted.
+    ///
+    /// A `Program` is `Send`, so this is a convenient way to run a group of
+    /// programs on their own threads. See `examples/ring.rs` for a network of
+    /// programs on threads that talk through channels.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::mpsc;
+    /// use std::thread;
+    ///
+    /// let (input, rx) = mpsc::channel();
+    /// let (tx, output) = mpsc::channel();
+    /// let mut program = intcode::Program::new(vec![3, 9, 1002, 9, 2, 9, 4, 9, 99]);
+    /// let handle = thread::spawn(move || program.run_connected(&rx, &tx));
+    ///
+    /// input.send(21).unwrap();
+    /// assert_eq!(output.recv(), Ok(42));
+    /// handle.join().unwrap();
+    /// ```
+    pub fn run_connected(&mut self, input: &Receiver<Number>, output: &Sender<Number>) {
+        loop {
+            self.run_without_pausing();
+            while let Some(i) = self.get_output() {
+                if output.send(i).is_err() {
+                    return;
+                }
+            }
+            if self.halted() {
+                return;
+            }
+            match input.recv() {
+                Ok(i) => self.push_input(i),
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_program_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<Program>();
+}
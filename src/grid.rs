@@ -0,0 +1,19 @@
+/// Returns the smallest and the largest corner of the smallest rectangle that
+/// contains all the points, both inclusive, or `None` if there are no points.
+///
+/// This is what's needed to print a grid of points that were collected in a
+/// map, like the panels painted in day 11.
+///
+/// # Example
+/// ```
+/// let points = [(2, -1), (0, 3), (-4, 1)];
+/// assert_eq!(intcode::bounding_box(points), Some(((-4, -1), (2, 3))));
+/// assert_eq!(intcode::bounding_box([]), None);
+/// ```
+pub fn bounding_box(points: impl IntoIterator<Item = (i64, i64)>) -> Option<((i64, i64), (i64, i64))> {
+    let mut points = points.into_iter();
+    let (x, y) = points.next()?;
+    Some(points.fold(((x, y), (x, y)), |((min_x, min_y), (max_x, max_y)), (x, y)| {
+        ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+    }))
+}
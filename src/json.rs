@@ -0,0 +1,395 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::{Number, ParseError, Program, State};
+
+/// A JSON value, limited to what `Program::to_json` writes.
+enum Value {
+    Bool(bool),
+    Number(Number),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+fn invalid(message: &str) -> ParseError {
+    ParseError::InvalidJson(message.to_string())
+}
+
+/// How deeply arrays and objects may be nested. `Program::to_json` never goes
+/// past 2, and without a limit deeply nested input would overflow the stack.
+const MAX_DEPTH: usize = 16;
+
+struct Reader<'a> {
+    text: &'a [u8],
+    pos: usize,
+    /// The number of arrays and objects the reader is currently inside.
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), ParseError> {
+        if self.peek() != Some(b) {
+            return Err(ParseError::InvalidJson(format!("expected '{}' at byte {}", b as char, self.pos)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        if matches!(self.peek(), Some(b'{' | b'[')) {
+            if self.depth == MAX_DEPTH {
+                return Err(ParseError::InvalidJson(format!("nested too deeply at byte {}", self.pos)));
+            }
+            self.depth += 1;
+            let value = self.nested_value();
+            self.depth -= 1;
+            return value;
+        }
+        self.nested_value()
+    }
+
+    fn nested_value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some(b'{') => {
+                let mut members = Vec::new();
+                self.list(b'{', b'}', |r| {
+                    let key = r.string()?;
+                    r.expect(b':')?;
+                    members.push((key, r.value()?));
+                    Ok(())
+                })?;
+                Ok(Value::Object(members))
+            }
+            Some(b'[') => {
+                let mut items = Vec::new();
+                self.list(b'[', b']', |r| {
+                    items.push(r.value()?);
+                    Ok(())
+                })?;
+                Ok(Value::Array(items))
+            }
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') if self.text[self.pos..].starts_with(b"true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(b'f') if self.text[self.pos..].starts_with(b"false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.text.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+                text.parse()
+                    .map(Value::Number)
+                    .map_err(|_| ParseError::InvalidJson(format!("invalid number {:?}", text)))
+            }
+            Some(_) => Err(ParseError::InvalidJson(format!("unexpected character at byte {}", self.pos))),
+            None => Err(invalid("unexpected end of input")),
+        }
+    }
+
+    /// Reads a comma-separated list of items between `open` and `close`,
+    /// calling `item` to read each one.
+    fn list<F>(&mut self, open: u8, close: u8, mut item: F) -> Result<(), ParseError>
+    where
+        F: FnMut(&mut Self) -> Result<(), ParseError>,
+    {
+        self.expect(open)?;
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            } else {
+                return self.expect(close);
+            }
+        }
+    }
+
+    /// Reads a string. Escape sequences are not supported, since nothing
+    /// `Program::to_json` writes needs them.
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        loop {
+            match self.text.get(self.pos) {
+                Some(b'"') => break,
+                Some(b'\\') => return Err(invalid("escape sequences are not supported")),
+                Some(_) => self.pos += 1,
+                None => return Err(invalid("unterminated string")),
+            }
+        }
+        let s = String::from_utf8_lossy(&self.text[start..self.pos]).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+}
+
+impl Value {
+    fn number(&self) -> Option<Number> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn numbers(&self) -> Option<Vec<Number>> {
+        match self {
+            Value::Array(items) => items.iter().map(Value::number).collect(),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of a JSON object, looked up by name.
+struct Fields(Vec<(String, Value)>);
+
+impl Fields {
+    fn get(&self, name: &str) -> Result<&Value, ParseError> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| ParseError::InvalidJson(format!("missing field {:?}", name)))
+    }
+
+    fn bool(&self, name: &str) -> Result<bool, ParseError> {
+        match self.get(name)? {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(ParseError::InvalidJson(format!("field {:?} is not a boolean", name))),
+        }
+    }
+
+    fn number(&self, name: &str) -> Result<Number, ParseError> {
+        self.get(name)?
+            .number()
+            .ok_or_else(|| ParseError::InvalidJson(format!("field {:?} is not a number", name)))
+    }
+
+    fn index(&self, name: &str) -> Result<usize, ParseError> {
+        usize::try_from(self.number(name)?)
+            .map_err(|_| ParseError::InvalidJson(format!("field {:?} is not a valid index", name)))
+    }
+
+    fn numbers(&self, name: &str) -> Result<Vec<Number>, ParseError> {
+        self.get(name)?
+            .numbers()
+            .ok_or_else(|| ParseError::InvalidJson(format!("field {:?} is not an array of numbers", name)))
+    }
+}
+
+fn write_numbers(json: &mut String, values: &[Number]) {
+    json.push('[');
+    for (i, val) in values.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "{}", val).unwrap();
+    }
+    json.push(']');
+}
+
+impl Program {
+    /// Serializes the program's state to a JSON object, which `from_json`
+    /// turns back into the same program.
+    ///
+    /// The object holds the stack pointer, the relative base, the state, the
+    /// memory, the initial tape that `reset` restores, both queues along with
+    /// how far they have been consumed, and whether input was closed with
+    /// `close_input`. Memory beyond the end of the
+    /// tape is written as a list of `[address, value]` pairs. Settings such
+    /// as the input policy, functions set with methods like `on_output`, and
+    /// the statistics in `profile_report` are not included.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 5, 4, 5, 99, 0]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(
+    ///     program.to_json(),
+    ///     concat!(
+    ///         r#"{"sp":0,"relative_base":0,"state":"WaitingForInput","memory":[3,5,4,5,99,0],"#,
+    ///         r#""initial":[3,5,4,5,99,0],"extra_memory":[],"input":[],"input_pos":0,"#,
+    ///         r#""input_closed":false,"output":[],"output_pos":0}"#,
+    ///     ),
+    /// );
+    /// ```
+    pub fn to_json(&self) -> String {
+        let state = match self.state {
+            State::Running => "Running",
+            State::WaitingForInput => "WaitingForInput",
+            State::Halted => "Halted",
+        };
+        let mut json = String::new();
+        write!(json, r#"{{"sp":{},"relative_base":{},"state":"{}","#, self.sp, self.relative_base, state).unwrap();
+        json.push_str(r#""memory":"#);
+        write_numbers(&mut json, &self.tape());
+        json.push_str(r#","initial":"#);
+        write_numbers(&mut json, &self.initial);
+        json.push_str(r#","extra_memory":["#);
+        for (i, (pos, val)) in self.extra_cells().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "[{},{}]", pos, val).unwrap();
+        }
+        json.push_str(r#"],"input":"#);
+        write_numbers(&mut json, &self.input);
+        write!(json, r#","input_pos":{},"input_closed":{},"output":"#, self.input_pos, self.input_closed).unwrap();
+        write_numbers(&mut json, &self.output);
+        write!(json, r#","output_pos":{}}}"#, self.output_pos).unwrap();
+        json
+    }
+
+    /// Creates a program from JSON in the format written by `to_json`. Other
+    /// settings are the same as for `Program::new`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::InvalidJson` if the text isn't valid JSON, or if a
+    /// field is missing or holds something it can't.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 5, 4, 5, 99, 0]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// let mut copy = intcode::Program::from_json(&program.to_json()).unwrap();
+    /// copy.push_input(8);
+    /// copy.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(copy.get_output(), Some(8));
+    /// ```
+    pub fn from_json(json: &str) -> Result<Program, ParseError> {
+        let mut reader = Reader { text: json.as_bytes(), pos: 0, depth: 0 };
+        let value = reader.value()?;
+        if reader.peek().is_some() {
+            return Err(invalid("unexpected text after the object"));
+        }
+        let fields = match value {
+            Value::Object(members) => Fields(members),
+            _ => return Err(invalid("expected an object")),
+        };
+
+        let state = match fields.get("state")? {
+            Value::String(s) if s == "Running" => State::Running,
+            Value::String(s) if s == "WaitingForInput" => State::WaitingForInput,
+            Value::String(s) if s == "Halted" => State::Halted,
+            _ => return Err(invalid("field \"state\" is not a valid state")),
+        };
+        let mut extra_memory = Vec::new();
+        let pairs = match fields.get("extra_memory")? {
+            Value::Array(pairs) => pairs,
+            _ => return Err(invalid("field \"extra_memory\" is not an array")),
+        };
+        for pair in pairs {
+            match pair.numbers().as_deref() {
+                Some(&[pos, val]) if pos >= 0 => {
+                    extra_memory.push((pos as usize, val));
+                }
+                _ => return Err(invalid("field \"extra_memory\" holds something other than an address and a value")),
+            }
+        }
+
+        let input = fields.numbers("input")?;
+        let input_pos = fields.index("input_pos")?;
+        let input_closed = fields.bool("input_closed")?;
+        let output = fields.numbers("output")?;
+        let output_pos = fields.index("output_pos")?;
+        if input_pos > input.len() || output_pos > output.len() {
+            return Err(invalid("a queue position is past the end of its queue"));
+        }
+
+        let mut program = Program::new(fields.numbers("initial")?);
+        let memory = fields.numbers("memory")?;
+        if memory != *program.initial {
+            program.program = Arc::new(memory);
+        }
+        program.sp = fields.index("sp")?;
+        program.relative_base = fields.number("relative_base")?;
+        program.state = state;
+        for (pos, val) in extra_memory {
+            program.set_extra(pos, val);
+        }
+        program.input = input;
+        program.input_pos = input_pos;
+        program.input_closed = input_closed;
+        program.output = output;
+        program.output_pos = output_pos;
+        Ok(program)
+    }
+}
+
+#[test]
+fn test_from_json_errors() {
+    let json = Program::new(vec![1, 2]).to_json();
+    assert!(Program::from_json(&json).is_ok());
+    for bad in [
+        &json[..json.len() - 1],
+        &json.replace("Running", "Walking"),
+        &json.replace(r#""sp":0"#, r#""sp":-1"#),
+        &json.replace(r#""input_pos":0"#, r#""input_pos":1"#),
+        &json.replace("false", "0"),
+        "[1,2]",
+    ] {
+        assert!(matches!(Program::from_json(bad), Err(ParseError::InvalidJson(_))));
+    }
+}
+
+#[test]
+fn test_from_json_limits_nesting() {
+    let deep = "[".repeat(200_000);
+    assert!(matches!(Program::from_json(&deep), Err(ParseError::InvalidJson(_))));
+}
+
+#[test]
+fn test_json_round_trip_mid_run() {
+    // Sets the relative base, writes 7 beyond the tape, outputs 1 and 2, then
+    // waits for input and outputs the 7 and the input.
+    let tape = vec![109, 5, 21101, 3, 4, 100, 104, 1, 104, 2, 3, 200, 204, 100, 4, 200, 99];
+    let mut original = Program::new(tape);
+    original.run_till_halted_or_blocked().unwrap();
+    assert_eq!(original.get_output(), Some(1));
+
+    let mut copy = Program::from_json(&original.to_json()).unwrap();
+    assert!(copy == original);
+    for program in [&mut original, &mut copy] {
+        program.push_input(9);
+        program.run_till_halted_or_blocked().unwrap();
+        assert!(program.halted());
+    }
+    assert_eq!(copy.effective_memory(), original.effective_memory());
+    assert_eq!(copy.pending_output(), &[2, 7, 9]);
+    assert_eq!(copy.pending_output(), original.pending_output());
+    assert!(copy == original);
+}
+
+#[test]
+fn test_json_keeps_closed_input() {
+    let mut original = Program::new(vec![3, 0, 99]);
+    original.close_input();
+    let mut copy = Program::from_json(&original.to_json()).unwrap();
+    assert!(copy == original);
+    copy.run_till_halted_or_blocked().unwrap();
+    assert!(copy.halted());
+}
@@ -1,27 +1,73 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+mod arcade;
+mod ascii;
+mod builder;
+mod channel;
+mod grid;
+mod json;
+mod network;
+pub mod opcode;
+mod parse;
+mod stream;
+mod template;
+
+pub use arcade::{count_tiles, Arcade};
+pub use ascii::AsciiOutput;
+pub use builder::ProgramBuilder;
+pub use grid::bounding_box;
+pub use network::{Network, Packet, NAT_ADDRESS};
+pub use parse::ParseError;
+pub use stream::{InputMode, OutputMode};
+pub use template::Template;
 
 /// All values in any program's memory are of this type.
 pub type Number = i64;
 
-#[derive(Debug)]
-enum ParameterMode {
+/// The number of instructions `Program::is_quine` runs a program for before it
+/// gives up.
+pub const QUINE_STEP_BUDGET: u64 = 1_000_000;
+
+/// How an instruction's parameter is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterMode {
+    /// The parameter is the address of the value.
     Position,
+    /// The parameter is the value itself.
     Immediate,
+    /// The parameter plus the relative base is the address of the value.
     Relative,
 }
 
 impl ParameterMode {
-    fn from(i: Number) -> ParameterMode {
+    fn decode(i: Number) -> Option<ParameterMode> {
         match i {
-            0 => ParameterMode::Position,
-            1 => ParameterMode::Immediate,
-            2 => ParameterMode::Relative,
-            _ => panic!("Unknown parameter mode: {}", i)
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::Immediate),
+            2 => Some(ParameterMode::Relative),
+            _ => None,
+        }
+    }
+
+    /// The character put in front of a parameter in disassembly to show its
+    /// mode.
+    fn prefix(&self) -> char {
+        match self {
+            ParameterMode::Position => '@',
+            ParameterMode::Immediate => '#',
+            ParameterMode::Relative => '~',
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Opcode {
     Add,
     Multiply,
@@ -36,19 +82,64 @@ enum Opcode {
 }
 
 impl Opcode {
-    fn from(i: Number) -> Opcode {
+    const ALL: [Opcode; 10] = [
+        Opcode::Add,
+        Opcode::Multiply,
+        Opcode::Input,
+        Opcode::Output,
+        Opcode::JumpIfTrue,
+        Opcode::JumpIfFalse,
+        Opcode::LessThan,
+        Opcode::Equals,
+        Opcode::RelativeBaseOffset,
+        Opcode::Halt,
+    ];
+
+    fn decode(i: Number) -> Option<Opcode> {
         match i {
-            1 => Opcode::Add,
-            2 => Opcode::Multiply,
-            3 => Opcode::Input,
-            4 => Opcode::Output,
-            5 => Opcode::JumpIfTrue,
-            6 => Opcode::JumpIfFalse,
-            7 => Opcode::LessThan,
-            8 => Opcode::Equals,
-            9 => Opcode::RelativeBaseOffset,
-            99 => Opcode::Halt,
-            _ => { panic!("Unknown opcode: {}", i) },
+            opcode::ADD => Some(Opcode::Add),
+            opcode::MULTIPLY => Some(Opcode::Multiply),
+            opcode::INPUT => Some(Opcode::Input),
+            opcode::OUTPUT => Some(Opcode::Output),
+            opcode::JUMP_IF_TRUE => Some(Opcode::JumpIfTrue),
+            opcode::JUMP_IF_FALSE => Some(Opcode::JumpIfFalse),
+            opcode::LESS_THAN => Some(Opcode::LessThan),
+            opcode::EQUALS => Some(Opcode::Equals),
+            opcode::RELATIVE_BASE_OFFSET => Some(Opcode::RelativeBaseOffset),
+            opcode::HALT => Some(Opcode::Halt),
+            _ => None,
+        }
+    }
+
+    /// The number of memory cells taken up by an instruction with this opcode,
+    /// including the opcode itself.
+    fn width(&self) -> usize {
+        match self {
+            Opcode::Add => 4,
+            Opcode::Multiply => 4,
+            Opcode::Input => 2,
+            Opcode::Output => 2,
+            Opcode::JumpIfTrue => 3,
+            Opcode::JumpIfFalse => 3,
+            Opcode::LessThan => 4,
+            Opcode::Equals => 4,
+            Opcode::RelativeBaseOffset => 2,
+            Opcode::Halt => 1,
+        }
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Opcode::Add => "ADD",
+            Opcode::Multiply => "MUL",
+            Opcode::Input => "IN",
+            Opcode::Output => "OUT",
+            Opcode::JumpIfTrue => "JT",
+            Opcode::JumpIfFalse => "JF",
+            Opcode::LessThan => "LT",
+            Opcode::Equals => "EQ",
+            Opcode::RelativeBaseOffset => "RBO",
+            Opcode::Halt => "HALT",
         }
     }
 }
@@ -62,317 +153,3512 @@ struct Instruction {
 }
 
 impl Instruction {
-    fn from(mut i: Number) -> Instruction {
-        let opcode = Opcode::from(i % 100);
-        i /= 100;
-        let param1 = ParameterMode::from(i % 10);
-        i /= 10;
-        let param2 = ParameterMode::from(i % 10);
-        i /= 10;
-        let param3 = ParameterMode::from(i % 10);
+    /// Decodes an instruction, or returns `None` if the number isn't a valid
+    /// instruction.
+    fn decode(i: Number) -> Option<Instruction> {
+        if i < 0 {
+            return None;
+        }
+        Some(Instruction {
+            opcode: Opcode::decode(i % 100)?,
+            param1: ParameterMode::decode(i / 100 % 10)?,
+            param2: ParameterMode::decode(i / 1000 % 10)?,
+            param3: ParameterMode::decode(i / 10000 % 10)?,
+        })
+    }
+}
+
+/// Walks over a tape from the start, decoding one instruction at a time and
+/// skipping over its parameters. A cell that doesn't decode to an instruction
+/// is treated as data and yields `None`, after which decoding resumes at the
+/// next cell.
+struct Disassembly<'a> {
+    program: &'a Program,
+    addr: usize,
+}
+
+impl<'a> Iterator for Disassembly<'a> {
+    type Item = (usize, Option<Instruction>);
 
-        Instruction {
-            opcode,
-            param1,
-            param2,
-            param3
+    fn next(&mut self) -> Option<Self::Item> {
+        let addr = self.addr;
+        if addr >= self.program.program.len() {
+            return None;
         }
+        let instruction = Instruction::decode(self.program.get_mem(addr));
+        self.addr += instruction.as_ref().map_or(1, |i| i.opcode.width());
+        Some((addr, instruction))
     }
 }
 
-#[derive(Debug)]
-enum ProgramState {
+/// An instruction from a program's memory, decoded but not executed. It
+/// displays the way instructions are shown in traces, for example
+/// `ADD @4 #3 -> ~1`, where `@`, `#` and `~` mark position, immediate and
+/// relative mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    /// The address of the instruction.
+    pub addr: usize,
+    /// The opcode, one of the numbers in `intcode::opcode`, without the
+    /// parameter modes.
+    pub opcode: Number,
+    /// The opcode's name, such as `ADD` or `HALT`.
+    pub mnemonic: &'static str,
+    /// The raw values of the parameters, in order, and the mode of each.
+    pub params: Vec<(ParameterMode, Number)>,
+}
+
+impl DecodedInstruction {
+    /// Returns the index in `params` of the parameter the instruction writes
+    /// to, if it writes to memory.
+    fn write_target(&self) -> Option<usize> {
+        match self.opcode {
+            opcode::ADD | opcode::MULTIPLY | opcode::LESS_THAN | opcode::EQUALS => Some(2),
+            opcode::INPUT => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the parameter the instruction writes to is in
+    /// immediate mode. Such an instruction can't be executed: running it
+    /// fails with a panic, or halts the program if an error handler is set.
+    ///
+    /// The instruction then displays with a note saying it is invalid.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![11101, 1, 2, 3, 99]);
+    /// let instruction = program.current_instruction().unwrap();
+    /// assert!(instruction.writes_to_immediate());
+    /// assert_eq!(
+    ///     instruction.to_string(),
+    ///     "ADD #1 #2 -> #3 ; invalid: writes to a parameter in immediate mode",
+    /// );
+    /// ```
+    pub fn writes_to_immediate(&self) -> bool {
+        self.write_target()
+            .and_then(|n| self.params.get(n))
+            .is_some_and(|(mode, _)| *mode == ParameterMode::Immediate)
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        let target = self.write_target();
+        for (n, (mode, value)) in self.params.iter().enumerate() {
+            let separator = if Some(n) == target { " -> " } else { " " };
+            write!(f, "{}{}{}", separator, mode.prefix(), value)?;
+        }
+        if self.writes_to_immediate() {
+            write!(f, " ; invalid: writes to a parameter in immediate mode")?;
+        }
+        Ok(())
+    }
+}
+
+/// The state a program is in. With the `serde` feature turned on, this can be
+/// serialized, for example to log state changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum State {
+    /// The program can execute its next instruction.
     Running,
+    /// The program needs input before it can continue.
+    WaitingForInput,
+    /// The program has executed a "halt" instruction, or has run out of input
+    /// under `InputPolicy::Halt`.
+    Halted,
+}
+
+/// Why `Program::run_to_breakpoint` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakReason {
+    /// The program reached a breakpoint at this address, and the instruction
+    /// there has not been executed yet.
+    Breakpoint(usize),
+    /// The program halted.
+    Halted,
+    /// The program needs input before it can continue.
+    WaitingForInput,
+    /// The program wrote `new` to the watched address `addr`, which used to
+    /// hold `old`. The instruction that wrote it has been executed.
+    Watchpoint { addr: usize, old: Number, new: Number },
+}
+
+/// What happened when `Program::run_to_io` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoEvent {
+    /// The program output this value, which has been consumed.
+    Output(Number),
+    /// The program needs input before it can continue.
+    NeedInput,
+    /// The program halted.
+    Halted,
+}
+
+/// Why `Program::run_with_step_limit` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The program halted.
+    Halted,
+    /// The program needs input before it can continue.
     WaitingForInput,
+    /// The program executed as many instructions as it was allowed to, and
+    /// can still continue.
+    StepLimitReached,
+}
+
+/// What `Program::run_result` found after running a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    /// The program's memory, from address 0 up to and including the highest
+    /// address that holds a value, with any gaps filled with 0.
+    pub memory: Vec<Number>,
+    /// The outputs that hadn't been consumed yet, which are now consumed.
+    pub outputs: Vec<Number>,
+    /// Whether the program halted, as opposed to needing input.
+    pub halted: bool,
+    /// How many instructions were executed during the run.
+    pub steps: usize,
+}
+
+/// What a program does when it executes an "input" opcode while its input
+/// queue is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputPolicy {
+    /// Wait for more input, or halt if the input was closed with
+    /// `Program::close_input`. This is the default.
+    #[default]
+    Block,
+    /// Fail with `RunError::InputExhausted`.
+    Error,
+    /// Read the given value instead, without blocking.
+    Value(Number),
+    /// Treat the end of the input as the end of the program, and halt. The
+    /// stack pointer is left on the "input" instruction.
+    ///
+    /// This means a program can halt without executing a "halt" instruction,
+    /// and that giving it more input afterwards won't make it continue.
+    Halt,
+}
+
+/// Something a program did, as recorded in its event log. See
+/// `Program::enable_event_log`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The program executed the instruction at `addr`, which has the given
+    /// opcode. The opcode is one of the numbers in `intcode::opcode`, without
+    /// the parameter modes.
+    Exec { addr: usize, opcode: Number },
+    /// The program read this value from its input.
+    Input(Number),
+    /// The program output this value.
+    Output(Number),
+    /// The program halted.
+    Halt,
+}
+
+/// An error encountered while running a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunError {
+    /// The program needed input, none was available, and the input policy is
+    /// `InputPolicy::Error`.
+    InputExhausted,
+    /// With strict reads turned on, the program read from this address, which
+    /// lies beyond the initial tape and was never written to.
+    UninitializedRead(usize),
+    /// The program used this negative number as an address, either as a
+    /// parameter or as the target of a jump.
+    NegativeAddress(Number),
+    /// The program used an address that is too large to be represented on
+    /// this platform, or a parameter in relative mode that overflows when the
+    /// relative base is added to it, or it moved the relative base so far that
+    /// it overflows.
+    AddressOverflow,
+    /// The program was about to output `value` as its output number `index`,
+    /// counting from 0, which isn't the output given to `set_expected_output`.
+    /// `expected` is `None` if no more output was expected.
+    UnexpectedOutput {
+        index: usize,
+        value: Number,
+        expected: Option<Number>,
+    },
+    /// Reading from the reader given to `set_input_reader` failed, or what it
+    /// read couldn't be turned into input.
+    InputFailed(io::ErrorKind),
+    /// Writing an output to the writer given to `set_output_writer` failed.
+    OutputFailed(io::ErrorKind),
+    /// The instruction at address `sp` has an opcode that doesn't exist, and
+    /// that wasn't added with `register_opcode`.
+    UnknownOpcode { sp: usize, opcode: Number },
+    /// The instruction at address `sp` has a parameter mode that doesn't
+    /// exist.
+    UnknownParameterMode { sp: usize, mode: Number },
+    /// The instruction at address `sp` writes to a parameter in immediate
+    /// mode.
+    WriteToImmediate { sp: usize },
+    /// The result of the "add" or "multiply" instruction at address `sp` is
+    /// too large or too small to fit in a `Number`.
+    ArithmeticOverflow { sp: usize },
+    /// The program was asked to execute an instruction after it had already
+    /// halted.
     Halted,
 }
 
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::InputExhausted => write!(f, "input exhausted"),
+            RunError::UninitializedRead(pos) => {
+                write!(f, "read from uninitialized address {}", pos)
+            }
+            RunError::NegativeAddress(addr) => write!(f, "negative address {}", addr),
+            RunError::AddressOverflow => write!(f, "address out of range"),
+            RunError::UnexpectedOutput { index, value, expected: Some(expected) } => {
+                write!(f, "output {} is {}, expected {}", index, value, expected)
+            }
+            RunError::UnexpectedOutput { index, value, expected: None } => {
+                write!(f, "output {} is {}, expected no more output", index, value)
+            }
+            RunError::InputFailed(kind) => write!(f, "could not read input: {}", kind),
+            RunError::OutputFailed(kind) => write!(f, "could not write output: {}", kind),
+            RunError::UnknownOpcode { sp, opcode } => {
+                write!(f, "unknown opcode {} at address {}", opcode, sp)
+            }
+            RunError::UnknownParameterMode { sp, mode } => {
+                write!(f, "unknown parameter mode {} at address {}", mode, sp)
+            }
+            RunError::WriteToImmediate { sp } => {
+                write!(f, "write to a parameter in immediate mode at address {}", sp)
+            }
+            RunError::ArithmeticOverflow { sp } => {
+                write!(f, "arithmetic overflow at address {}", sp)
+            }
+            RunError::Halted => write!(f, "program has already halted"),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+type WriteHook = Box<dyn FnMut(usize, Number, Number) + Send>;
+type TraceHook = Box<dyn FnMut(usize, &str) + Send>;
+type OutputHook = Box<dyn FnMut(Number) + Send>;
+type RelativeBaseHook = Box<dyn FnMut(Number, Number) + Send>;
+type ErrorHandler = Box<dyn FnMut(&str) + Send>;
+type BreakCondition = Box<dyn FnMut(&Program) -> bool + Send>;
+type OpcodeHandler = Box<dyn FnMut(&mut Program) + Send>;
+
+/// Statistics about a program's execution, kept up to date as it runs.
+#[derive(Default, Clone)]
+struct Counters {
+    steps: u64,
+    /// Indexed by `Opcode as usize`.
+    opcodes: [u64; Opcode::ALL.len()],
+    reads: Cell<u64>,
+    writes: u64,
+    relative_base_changes: u64,
+    high_water_mark: Cell<usize>,
+    /// Which cells of the tape and of the contiguous block of extra memory
+    /// have been written to. This is left empty until the first write, and
+    /// grows along with the tape and the block.
+    written: Vec<bool>,
+    /// Which cells beyond those have been written to.
+    written_sparse: BTreeSet<usize>,
+    distinct_writes: usize,
+}
+
+/// A summary of how a program has executed so far, as returned by
+/// `Program::profile_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfilingReport {
+    /// The number of instructions executed.
+    pub instructions: u64,
+    /// The number of times each opcode was executed, in the order of the
+    /// opcodes' numbers, with opcodes named as they are in assembly.
+    pub opcode_counts: Vec<(&'static str, u64)>,
+    /// The number of memory reads, including reading the instructions
+    /// themselves.
+    pub memory_reads: u64,
+    /// The number of memory writes.
+    pub memory_writes: u64,
+    /// The number of times the relative base was adjusted.
+    pub relative_base_changes: u64,
+    /// The highest address read from or written to.
+    pub high_water_mark: usize,
+}
+
+impl fmt::Display for ProfilingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "instructions: {}", self.instructions)?;
+        let counts: Vec<_> = self.opcode_counts
+            .iter()
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        writeln!(f, "opcodes: {}", counts.join(", "))?;
+        writeln!(f, "memory reads: {}", self.memory_reads)?;
+        writeln!(f, "memory writes: {}", self.memory_writes)?;
+        writeln!(f, "relative base changes: {}", self.relative_base_changes)?;
+        write!(f, "high-water mark: {}", self.high_water_mark)
+    }
+}
+
+struct Trace {
+    opcodes: Option<HashSet<String>>,
+    f: TraceHook,
+}
+
+struct InputReader {
+    reader: Box<dyn BufRead + Send>,
+    mode: InputMode,
+}
+
+struct OutputWriter {
+    writer: Box<dyn Write + Send>,
+    mode: OutputMode,
+    /// Whether the last thing written ended a line.
+    at_line_start: bool,
+}
+
+/// The largest number of cells `ExtraMemory` keeps in its contiguous block,
+/// unless more were asked for with `ProgramBuilder::memory_hint`.
+const DENSE_MEMORY_LIMIT: usize = 1 << 20;
+
+/// The number of written cells a program keeps in its overlay before it makes
+/// its own copy of the memory it shares, unless a sixteenth of that memory is
+/// more.
+const OVERLAY_LIMIT: usize = 64;
+
+/// Memory beyond the end of the tape. The addresses from `start` on are kept
+/// in one contiguous block, which grows when the program writes just past its
+/// end, up to `DENSE_MEMORY_LIMIT` cells. Writes further out go to a map, so
+/// that a single write to a far address doesn't allocate everything in
+/// between. A cell in the block that was never written holds `None`.
+#[derive(Debug, Clone, Default)]
+struct ExtraMemory {
+    start: usize,
+    cells: Vec<Option<Number>>,
+    /// Every address in here comes after the end of `cells`.
+    sparse: BTreeMap<usize, Number>,
+}
+
+impl ExtraMemory {
+    fn get(&self, pos: usize) -> Option<Number> {
+        let i = pos.checked_sub(self.start)?;
+        match self.cells.get(i) {
+            Some(&cell) => cell,
+            None => self.sparse.get(&pos).copied(),
+        }
+    }
+
+    fn set(&mut self, pos: usize, val: Number) {
+        if pos < self.start {
+            // The tape was replaced by a shorter one with `reload_code`.
+            self.cells.splice(0..0, vec![None; self.start - pos]);
+            self.start = pos;
+        }
+        let i = pos - self.start;
+        if i >= self.cells.len() {
+            if i >= DENSE_MEMORY_LIMIT || i >= (2 * self.cells.len()).max(1024) {
+                self.sparse.insert(pos, val);
+                return;
+            }
+            self.grow(i + 1);
+        }
+        self.cells[i] = Some(val);
+    }
+
+    /// Makes the contiguous block at least `len` cells long, moving cells from
+    /// the map into it where it now covers them.
+    fn grow(&mut self, len: usize) {
+        if len <= self.cells.len() {
+            return;
+        }
+        self.cells.resize(len, None);
+        let rest = self.sparse.split_off(&self.end());
+        for (pos, val) in std::mem::replace(&mut self.sparse, rest) {
+            self.cells[pos - self.start] = Some(val);
+        }
+    }
+
+    /// Returns the address just past the contiguous block.
+    fn end(&self) -> usize {
+        self.start + self.cells.len()
+    }
+
+    /// Forgets every value, but keeps the contiguous block as large as it is.
+    fn clear(&mut self) {
+        self.cells.fill(None);
+        self.sparse.clear();
+    }
+
+    /// Iterates over the cells that have been written, in order of address.
+    fn iter(&self) -> impl Iterator<Item = (usize, Number)> + '_ {
+        let start = self.start;
+        self.cells.iter()
+            .enumerate()
+            .filter_map(move |(i, cell)| cell.map(|val| (start + i, val)))
+            .chain(self.sparse.iter().map(|(&pos, &val)| (pos, val)))
+    }
+}
+
 /// Contains an Intcode program.
+///
+/// The tape is shared between a program, the copy of it kept for `reset`, and
+/// any forks made with `fork`, and so is the memory beyond the end of the
+/// tape. While memory is shared with another program, writes to it go to a
+/// small overlay that reads look at first. Only once the overlay grows too
+/// large does the program copy the memory, and fold the overlay into its copy.
+/// A program that shares its tape with no one but the copy for `reset` copies
+/// the tape the first time it writes to it.
 pub struct Program {
-    program: Vec<Number>,
+    program: Arc<Vec<Number>>,
+    initial: Arc<Vec<Number>>,
+    /// Cells written while `program` or `extra` was shared with another
+    /// program, which take the place of the values in there.
+    overlay: BTreeMap<usize, Number>,
     sp: usize,
     input: Vec<Number>,
     input_pos: usize,
+    /// Whether `close_input` was called, so no more input will come.
+    input_closed: bool,
     output: Vec<Number>,
     output_pos: usize,
-    state: ProgramState,
-    extra_memory: HashMap<usize, Number>,
+    state: State,
+    extra: Arc<ExtraMemory>,
     relative_base: Number,
+    counters: Counters,
+    pc_history: Vec<usize>,
+    pc_history_capacity: usize,
+    event_log: Option<Vec<Event>>,
+    input_policy: InputPolicy,
+    strict_reads: bool,
+    write_hook: Option<WriteHook>,
+    trace: Option<Trace>,
+    output_hook: Option<OutputHook>,
+    input_reader: Option<InputReader>,
+    output_writer: Option<OutputWriter>,
+    relative_base_hook: Option<RelativeBaseHook>,
+    queue_output: bool,
+    pause_on_output: bool,
+    error_handler: Option<ErrorHandler>,
+    breakpoints: Vec<(usize, BreakCondition)>,
+    /// The address `run_to_breakpoint` last stopped at, so that running again
+    /// doesn't stop at the same breakpoint straight away.
+    paused_at: Option<usize>,
+    watchpoints: BTreeSet<usize>,
+    expected_output: Option<Vec<Number>>,
+    /// The number of outputs checked against `expected_output` so far.
+    expected_output_pos: usize,
+    /// The last write to a watched address, as address, old and new value.
+    watch_hit: Option<(usize, Number, Number)>,
+    /// Handlers for opcodes added with `register_opcode`, and the width of
+    /// each of those instructions.
+    custom_opcodes: BTreeMap<Number, (usize, OpcodeHandler)>,
+    /// The number of outputs there were when `is_idle` was last called.
+    idle_mark: Cell<usize>,
+    /// Whether the last input instruction read the value given by
+    /// `InputPolicy::Value` rather than queued input.
+    read_sentinel: bool,
+}
+
+/// Makes a copy with `Program::fork`, which can run independently of the
+/// original. Functions set with methods like `on_output` are not copied.
+///
+/// # Example
+/// ```
+/// let mut program = intcode::Program::new(vec![3, 5, 4, 5, 99, 0]);
+/// program.run_till_halted_or_blocked().unwrap();
+///
+/// let mut copy = program.clone();
+/// copy.push_input(4);
+/// copy.run_till_halted_or_blocked().unwrap();
+/// assert_eq!(copy.get_output(), Some(4));
+/// assert!(!program.halted());
+/// assert_eq!(program.get_output(), None);
+/// ```
+impl Clone for Program {
+    fn clone(&self) -> Program {
+        self.fork()
+    }
+}
+
+/// Compares what the program will do when it continues: its memory, the
+/// initial tape that `reset` restores, the stack pointer, the relative base,
+/// the state and both queues, along with how far they have been consumed and
+/// whether input was closed. Settings, functions set with methods like
+/// `on_output` and the statistics in `profile_report` are not compared.
+///
+/// This is what `to_json` saves, so a program restored with `from_json`
+/// equals the original.
+///
+/// # Example
+/// ```
+/// let mut program = intcode::Program::new(vec![3, 5, 4, 5, 99, 0]);
+/// program.run_till_halted_or_blocked().unwrap();
+///
+/// let copy = intcode::Program::from_json(&program.to_json()).unwrap();
+/// assert!(copy == program);
+/// program.push_input(1);
+/// assert!(copy != program);
+/// ```
+impl PartialEq for Program {
+    fn eq(&self, other: &Program) -> bool {
+        self.tape() == other.tape()
+            && self.initial == other.initial
+            && self.sp == other.sp
+            && self.relative_base == other.relative_base
+            && self.state == other.state
+            && self.input == other.input
+            && self.input_pos == other.input_pos
+            && self.input_closed == other.input_closed
+            && self.output == other.output
+            && self.output_pos == other.output_pos
+            && self.extra_cells().eq(other.extra_cells())
+    }
 }
 
 impl Program {
     /// Creates a new Intcode program.
     ///
-    /// The `Program` returned will start out as Running.
-    pub fn new(program_vec: Vec<Number>) -> Program {
+    /// The tape can be given as anything that turns into a `Vec<Number>`, such
+    /// as a `Vec`, a slice or an array. The `Program` returned will start out
+    /// as Running, unless the tape is empty. There is nothing to run then, so
+    /// it starts out as Halted.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new([104, 7, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(7));
+    ///
+    /// let tape: &[intcode::Number] = &[];
+    /// let program = intcode::Program::new(tape);
+    /// assert!(program.halted());
+    /// ```
+    pub fn new(program_vec: impl Into<Vec<Number>>) -> Program {
+        Program::from_tape(Arc::new(program_vec.into()))
+    }
+
+    /// Creates a new program from a tape that may be shared with other
+    /// programs.
+    fn from_tape(tape: Arc<Vec<Number>>) -> Program {
+        let extra = ExtraMemory { start: tape.len(), ..ExtraMemory::default() };
         Program {
-            program: program_vec,
+            state: Program::start_state(&tape),
+            program: Arc::clone(&tape),
+            initial: tape,
+            overlay: BTreeMap::new(),
             sp: 0,
             input: Vec::new(),
             input_pos: 0,
+            input_closed: false,
             output: Vec::new(),
             output_pos: 0,
-            state: ProgramState::Running,
-            extra_memory: HashMap::new(),
+            extra: Arc::new(extra),
             relative_base: 0,
+            counters: Counters::default(),
+            pc_history: Vec::new(),
+            pc_history_capacity: 0,
+            event_log: None,
+            input_policy: InputPolicy::Block,
+            strict_reads: false,
+            write_hook: None,
+            trace: None,
+            output_hook: None,
+            input_reader: None,
+            output_writer: None,
+            relative_base_hook: None,
+            queue_output: true,
+            pause_on_output: false,
+            error_handler: None,
+            breakpoints: Vec::new(),
+            paused_at: None,
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
+            expected_output: None,
+            expected_output_pos: 0,
+            custom_opcodes: BTreeMap::new(),
+            idle_mark: Cell::new(0),
+            read_sentinel: false,
         }
     }
 
-    /// Adds a value to the program's input queue.
-    pub fn push_input(&mut self, i: Number) {
-        self.input.push(i);
-        if let ProgramState::WaitingForInput = self.state {
-            self.state = ProgramState::Running;
+    /// Restores the program to the state it was in right after it was created.
+    ///
+    /// Memory is restored to the initial tape, the input and output queues are
+    /// emptied, and the program will be Running again.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![4, 3, 99, 1]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert!(program.halted());
+    ///
+    /// program.reset();
+    /// assert!(!program.halted());
+    /// assert_eq!(program.get_output(), None);
+    /// ```
+    pub fn reset(&mut self) {
+        self.program = Arc::clone(&self.initial);
+        self.overlay.clear();
+        self.sp = 0;
+        self.input.clear();
+        self.input_pos = 0;
+        self.input_closed = false;
+        self.output.clear();
+        self.output_pos = 0;
+        self.state = Program::start_state(&self.program);
+        Arc::make_mut(&mut self.extra).clear();
+        self.relative_base = 0;
+        self.counters = Counters::default();
+        self.pc_history.clear();
+        self.paused_at = None;
+        self.expected_output_pos = 0;
+        self.idle_mark.set(0);
+        self.read_sentinel = false;
+        if let Some(log) = self.event_log.as_mut() {
+            log.clear();
         }
     }
 
-    fn push_output(&mut self, i: Number) {
-        self.output.push(i);
+    /// Rewinds execution to the start of the program, but keeps its memory as
+    /// it is now.
+    ///
+    /// The stack pointer and the relative base go back to 0, the input and
+    /// output queues are emptied, and the program will be Running again. This
+    /// is for running an already modified program again with new input. Use
+    /// `reset` instead to also restore the memory to the initial tape. The
+    /// statistics in `profile_report` are kept.
+    ///
+    /// # Example
+    /// ```
+    /// // Increments address 100 and outputs it.
+    /// let mut program = intcode::Program::new(vec![1001, 100, 1, 100, 4, 100, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1));
+    ///
+    /// program.soft_reset();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(2));
+    /// ```
+    pub fn soft_reset(&mut self) {
+        self.sp = 0;
+        self.input.clear();
+        self.input_pos = 0;
+        self.input_closed = false;
+        self.output.clear();
+        self.output_pos = 0;
+        self.state = Program::start_state(&self.program);
+        self.relative_base = 0;
+        self.pc_history.clear();
+        self.paused_at = None;
+        self.expected_output_pos = 0;
+        self.idle_mark.set(0);
+        self.read_sentinel = false;
+        if let Some(log) = self.event_log.as_mut() {
+            log.clear();
+        }
     }
 
-    fn get_input(&mut self) -> Number {
-        let result = self.input[self.input_pos];
-        self.input_pos += 1;
-        result
+    fn start_state(tape: &[Number]) -> State {
+        if tape.is_empty() {
+            State::Halted
+        } else {
+            State::Running
+        }
     }
 
-    /// Return `true` if and only if this program's output queue is not empty.
-    pub fn has_output(&mut self) -> bool {
-        self.output_pos < self.output.len()
+    /// Replaces the program's code with a new tape, leaving everything else
+    /// alone. The new tape also becomes the one that `reset` restores.
+    ///
+    /// This is dangerous: the stack pointer, the relative base, the queues and
+    /// any memory beyond the end of the old tape are kept exactly as they are,
+    /// so the stack pointer may end up pointing into the middle of an
+    /// instruction, or past the end of the new code entirely. Memory that was
+    /// written beyond the end of the old tape is hidden by the new tape if the
+    /// new tape is long enough to cover it, and memory that was part of the
+    /// old tape but lies beyond the end of the new tape reads as 0.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 0, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// // The program is still blocked on the input instruction at address 0,
+    /// // so the replacement code picks up from there once input arrives.
+    /// program.reload_code(vec![3, 5, 4, 5, 99, 0]);
+    /// program.push_input(7);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(7));
+    /// ```
+    pub fn reload_code(&mut self, new_tape: Vec<Number>) {
+        self.fold_overlay();
+        let tape = Arc::new(new_tape);
+        self.program = Arc::clone(&tape);
+        self.initial = tape;
     }
 
-    /// Returns the last output generated, even if it has already been consumed.
-    /// If no outputs have been generated yet, this will return `None`.
+    /// Creates a copy of the program in its current state, which can then run
+    /// independently of the original.
+    ///
+    /// The copy shares its memory with the original, including memory beyond
+    /// the end of the tape, and each of them keeps the cells it writes after
+    /// that to itself, so forking doesn't copy memory. Other state is copied,
+    /// though, and that includes the input and output queues, the counters
+    /// behind `stats`, the pc history and the event log, so a fork costs more
+    /// the longer those are. Functions set with
+    /// `on_write`, `on_output`, `on_relative_base_change`, `set_trace`,
+    /// `set_error_handler` or `register_opcode` are not, and neither are the
+    /// reader and writer set with `set_input_reader` and `set_output_writer`,
+    /// breakpoints or watchpoints, so the copy starts out without them.
+    /// Cloning a program does the same.
     ///
     /// # Example
     /// ```
-    /// let mut program = intcode::Program::new(&vec![4, 3, 99, 1]);
-    /// program.run_till_halted_or_blocked();
-    /// assert_eq!(program.get_output(), Some(1));
-    /// assert_eq!(program.get_output(), None);
-    /// assert_eq!(program.last_output(), Some(1));
+    /// let mut program = intcode::Program::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// let mut fork = program.fork();
+    /// program.push_input(1);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// fork.push_input(10);
+    /// fork.run_till_halted_or_blocked().unwrap();
+    ///
+    /// assert_eq!(program.get_output(), Some(2));
+    /// assert_eq!(fork.get_output(), Some(11));
     /// ```
-    pub fn last_output(&mut self) -> Option<Number> {
-        if self.output.len() > 0 {
-            Some(self.output[self.output.len() - 1])
-        } else {
-            None
+    pub fn fork(&self) -> Program {
+        Program {
+            program: Arc::clone(&self.program),
+            initial: Arc::clone(&self.initial),
+            overlay: self.overlay.clone(),
+            sp: self.sp,
+            input: self.input.clone(),
+            input_pos: self.input_pos,
+            input_closed: self.input_closed,
+            output: self.output.clone(),
+            output_pos: self.output_pos,
+            state: self.state,
+            extra: Arc::clone(&self.extra),
+            relative_base: self.relative_base,
+            counters: self.counters.clone(),
+            pc_history: self.pc_history.clone(),
+            pc_history_capacity: self.pc_history_capacity,
+            event_log: self.event_log.clone(),
+            input_policy: self.input_policy,
+            strict_reads: self.strict_reads,
+            write_hook: None,
+            trace: None,
+            output_hook: None,
+            input_reader: None,
+            output_writer: None,
+            relative_base_hook: None,
+            queue_output: self.queue_output,
+            pause_on_output: self.pause_on_output,
+            error_handler: None,
+            breakpoints: Vec::new(),
+            paused_at: self.paused_at,
+            watchpoints: BTreeSet::new(),
+            watch_hit: None,
+            expected_output: self.expected_output.clone(),
+            expected_output_pos: self.expected_output_pos,
+            custom_opcodes: BTreeMap::new(),
+            idle_mark: self.idle_mark.clone(),
+            read_sentinel: self.read_sentinel,
         }
     }
 
-    /// Consumes and returns the first output in the output queue that has not
-    /// been consumed. If no outputs have been generated yet, this will return
-    /// `None`.
+    /// Creates a new program whose tape is the other program's memory as it is
+    /// now, including any memory beyond the end of its tape.
+    ///
+    /// Unlike `fork`, nothing else is copied: the new program starts from
+    /// address 0 with a relative base of 0 and empty queues, just like one
+    /// made with `Program::new`. This makes it possible to run a second phase
+    /// of a program on the memory left behind by the first.
+    ///
+    /// # Example
+    /// ```
+    /// // Increments address 9 and outputs it.
+    /// let mut first = intcode::Program::new(vec![1001, 9, 1, 9, 4, 9, 99, 0, 0, 5]);
+    /// first.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(first.get_output(), Some(6));
+    ///
+    /// let mut second = intcode::Program::from_memory_of(&first);
+    /// second.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(second.get_output(), Some(7));
+    /// ```
+    pub fn from_memory_of(other: &Program) -> Program {
+        Program::new(other.effective_memory())
+    }
+
+    /// Sets what the program does when it needs input but its input queue is
+    /// empty. See `InputPolicy` for the options.
     ///
     /// # Example
     /// ```
-    /// let mut program = intcode::Program::new(&vec![4, 5, 4, 6, 99, 1, 2]);
-    /// program.run_till_halted_or_blocked();
+    /// use intcode::{InputPolicy, Program};
+    ///
+    /// let mut program = Program::new(vec![3, 5, 4, 5, 99, 0]);
+    /// program.set_input_policy(InputPolicy::Value(-1));
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(-1));
+    /// assert!(program.halted());
+    /// ```
+    ///
+    /// With `InputPolicy::Halt`, running out of input ends the program:
+    ///
+    /// ```
+    /// use intcode::{InputPolicy, Program, State};
+    ///
+    /// // Echo every input until the input runs out.
+    /// let mut program = Program::new(vec![3, 100, 4, 100, 1105, 1, 0]);
+    /// program.set_input_policy(InputPolicy::Halt);
+    /// program.push_input(1);
+    /// program.push_input(2);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.state(), State::Halted);
     /// assert_eq!(program.get_output(), Some(1));
     /// assert_eq!(program.get_output(), Some(2));
-    /// assert_eq!(program.get_output(), None);
     /// ```
-    pub fn get_output(&mut self) -> Option<Number> {
-        if self.has_output() {
-            self.output_pos += 1;
-            Some(self.output[self.output_pos - 1])
-        } else {
-            None
-        }
+    pub fn set_input_policy(&mut self, policy: InputPolicy) {
+        self.input_policy = policy;
     }
 
-    fn increase_sp(&mut self) {
-        let instruction = Instruction::from(self.get_mem(self.sp));
-        self.sp += match instruction.opcode {
-            Opcode::Add => 4,
-            Opcode::Multiply => 4,
-            Opcode::Input => 2,
-            Opcode::Output => 2,
-            Opcode::JumpIfTrue => 3,
-            Opcode::JumpIfFalse => 3,
-            Opcode::LessThan => 4,
-            Opcode::Equals => 4,
-            Opcode::RelativeBaseOffset => 2,
-            Opcode::Halt => 0,
-        }
+    /// Turns strict reads on or off. They are off by default.
+    ///
+    /// Normally, memory beyond the end of the initial tape reads as 0 until it
+    /// is written to, as the Intcode spec says. With strict reads turned on,
+    /// reading such memory is an error instead, which helps track down
+    /// programs that read or jump somewhere they shouldn't. `try_run` reports
+    /// it as `RunError::UninitializedRead`, and the other run methods panic.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, RunError};
+    ///
+    /// let mut program = Program::new(vec![4, 100, 99]);
+    /// program.strict_reads(true);
+    /// assert_eq!(program.try_run(), Err(RunError::UninitializedRead(100)));
+    /// ```
+    pub fn strict_reads(&mut self, on: bool) {
+        self.strict_reads = on;
     }
 
-    fn param(&self, param: usize) -> Number {
-        let instruction = Instruction::from(self.get_mem(self.sp));
-        let value = self.get_mem(self.sp + param);
+    /// Sets a function to be called every time the program writes to memory.
+    ///
+    /// The function is passed the address that was written to, the value that
+    /// was there before, and the new value. Any function set earlier is
+    /// replaced.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let writes = Arc::new(Mutex::new(Vec::new()));
+    /// let log = Arc::clone(&writes);
+    ///
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 5, 99, 1]);
+    /// program.on_write(move |pos, old, new| log.lock().unwrap().push((pos, old, new)));
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(*writes.lock().unwrap(), vec![(5, 1, 5)]);
+    /// ```
+    pub fn on_write<F: FnMut(usize, Number, Number) + Send + 'static>(&mut self, f: F) {
+        self.write_hook = Some(Box::new(f));
+    }
 
-        let mode = match param {
-            1 => instruction.param1,
-            2 => instruction.param2,
-            3 => instruction.param3,
-            _ => unreachable!()
-        };
+    /// Sets a function to be called every time a "relative base offset"
+    /// instruction is executed. The function is passed the relative base
+    /// before and after the instruction. Any function set earlier is
+    /// replaced.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let changes = Arc::new(Mutex::new(Vec::new()));
+    /// let log = Arc::clone(&changes);
+    ///
+    /// let mut program = intcode::Program::new(vec![109, 10, 109, -3, 99]);
+    /// program.on_relative_base_change(move |old, new| log.lock().unwrap().push((old, new)));
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(*changes.lock().unwrap(), vec![(0, 10), (10, 7)]);
+    /// ```
+    pub fn on_relative_base_change<F: FnMut(Number, Number) + Send + 'static>(&mut self, f: F) {
+        self.relative_base_hook = Some(Box::new(f));
+    }
 
-        match mode {
-            ParameterMode::Position => { self.get_mem(value as usize) },
-            ParameterMode::Immediate => { value },
-            ParameterMode::Relative => { self.get_mem((self.relative_base + value) as usize) },
-        }
+    /// Sets a function to be called before every instruction is executed. It
+    /// is passed the instruction's address, and the instruction as assembly in
+    /// the same form `Display` shows it, such as `ADD @4 #3 -> ~1`. Any trace
+    /// set earlier is replaced. An input instruction that has to wait for
+    /// input is traced once, when it goes on to read.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let log = Arc::clone(&lines);
+    ///
+    /// let mut program = intcode::Program::new(vec![104, 5, 99]);
+    /// program.set_trace(move |addr, instruction| {
+    ///     log.lock().unwrap().push(format!("{}: {}", addr, instruction));
+    /// });
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(*lines.lock().unwrap(), vec!["0: OUT #5", "2: HALT"]);
+    /// ```
+    pub fn set_trace<F: FnMut(usize, &str) + Send + 'static>(&mut self, f: F) {
+        self.trace = Some(Trace { opcodes: None, f: Box::new(f) });
     }
 
-    /// Returns a position to write to or read from, taking into account the
-    /// parameter mode. The number passed in is the parameter that needs to be
+    /// Like `set_trace`, but only calls `f` for instructions with one of the
+    /// given opcodes. Opcodes are named as they are in assembly: `ADD`, `MUL`,
+    /// `IN`, `OUT`, `JT`, `JF`, `LT`, `EQ`, `RBO` and `HALT`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let lines = Arc::new(Mutex::new(Vec::new()));
+    /// let log = Arc::clone(&lines);
+    ///
+    /// let mut program = intcode::Program::new(vec![3, 9, 1001, 9, 1, 9, 4, 9, 99]);
+    /// program.set_trace_filtered(&["IN", "OUT"], move |addr, _| log.lock().unwrap().push(addr));
+    /// program.push_input(1);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(*lines.lock().unwrap(), vec![0, 6]);
+    /// ```
+    pub fn set_trace_filtered<F: FnMut(usize, &str) + Send + 'static>(&mut self, opcodes: &[&str], f: F) {
+        self.trace = Some(Trace {
+            opcodes: Some(opcodes.iter().map(|s| s.to_string()).collect()),
+            f: Box::new(f),
+        });
+    }
+
+    /// Returns the input that has been queued but not read by the program yet,
+    /// oldest first.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 100, 99]);
+    /// program.push_input(1);
+    /// program.push_input(2);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.pending_input(), &[2]);
+    /// ```
+    pub fn pending_input(&self) -> &[Number] {
+        &self.input[self.input_pos..]
+    }
+
+    /// Marks the program's input as closed, meaning no more input will come
+    /// after what is already queued.
+    ///
+    /// Once the queue is empty, an "input" instruction then halts the program
+    /// under `InputPolicy::Block`, instead of waiting for input forever, just
+    /// like it does under `InputPolicy::Halt`. The other policies work as
+    /// before, so with `InputPolicy::Error`, `try_run` returns
+    /// `RunError::InputExhausted`. Input pushed afterwards is still read if
+    /// the program hasn't halted yet. `reset` and `soft_reset` open the input
+    /// again.
+    ///
+    /// # Example
+    /// ```
+    /// // Outputs every input it reads.
+    /// let mut program = intcode::Program::new(vec![3, 100, 4, 100, 1105, 1, 0]);
+    /// program.push_input(1);
+    /// program.push_input(2);
+    /// program.close_input();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert!(program.halted());
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// ```
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Adds a value to the program's input queue.
+    pub fn push_input(&mut self, i: Number) {
+        self.input.push(i);
+        if let State::WaitingForInput = self.state {
+            self.state = State::Running;
+        }
+    }
+
+    /// Consumes all of `other`'s unconsumed outputs and adds them to this
+    /// program's input queue, in the order `other` produced them, after any
+    /// input that was already queued.
+    ///
+    /// Like `push_input`, this makes a program that is waiting for input
+    /// runnable again, as long as there was at least one output to take.
+    ///
+    /// # Example
+    /// ```
+    /// let mut first = intcode::Program::new(vec![104, 3, 104, 4, 99]);
+    /// let mut second = intcode::Program::new(vec![3, 11, 3, 12, 2, 11, 12, 13, 4, 13, 99]);
+    ///
+    /// first.run_till_halted_or_blocked().unwrap();
+    /// second.take_input_from(&mut first);
+    /// second.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(first.get_output(), None);
+    /// assert_eq!(second.get_output(), Some(12));
+    /// ```
+    pub fn take_input_from(&mut self, other: &mut Program) {
+        while let Some(i) = other.get_output() {
+            self.push_input(i);
+        }
+    }
+
+    /// Runs the program until it halts or needs input, and then moves all of
+    /// its unconsumed outputs to `other`'s input queue, the way
+    /// `take_input_from` does. Returns the number of values moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut first = intcode::Program::new(vec![104, 3, 104, 4, 99]);
+    /// let mut second = intcode::Program::new(vec![3, 11, 3, 12, 2, 11, 12, 13, 4, 13, 99]);
+    ///
+    /// assert_eq!(first.pipe_to(&mut second), 2);
+    /// second.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(second.get_output(), Some(12));
+    /// ```
+    pub fn pipe_to(&mut self, other: &mut Program) -> usize {
+        self.run_without_pausing();
+        let count = self.pending_output().len();
+        other.take_input_from(self);
+        count
+    }
+
+    /// Sets a function to be called with every value the program outputs, as
+    /// soon as it is output. The value is also added to the output queue as
+    /// usual, unless that was turned off with `set_queue_output`. Any function
+    /// set earlier is replaced.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.on_output(move |i| tx.send(i).unwrap());
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    /// assert_eq!(program.get_output(), Some(1));
+    /// ```
+    pub fn on_output<F: FnMut(Number) + Send + 'static>(&mut self, f: F) {
+        self.output_hook = Some(Box::new(f));
+    }
+
+    /// Sets a function to be called, instead of panicking, when the program
+    /// can't continue. Any function set earlier is replaced.
+    ///
+    /// This changes how a program ends: when the methods that would normally
+    /// panic, such as `run_or_panic`, hit any `RunError`, such as an unknown
+    /// opcode, they call the function with a description of the problem and
+    /// halt the program instead. The stack pointer is left on the instruction
+    /// that failed. Methods that return errors, like `try_run` and
+    /// `run_till_halted_or_blocked`, are not affected.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let errors = Arc::new(Mutex::new(Vec::new()));
+    /// let mut program = intcode::Program::new(vec![104, 1, 42, 99]);
+    /// let sink = Arc::clone(&errors);
+    /// program.set_error_handler(move |e| sink.lock().unwrap().push(e.to_string()));
+    ///
+    /// program.run_or_panic();
+    /// assert!(program.halted());
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(*errors.lock().unwrap(), vec!["unknown opcode 42 at address 2"]);
+    /// ```
+    pub fn set_error_handler<F: FnMut(&str) + Send + 'static>(&mut self, f: F) {
+        self.error_handler = Some(Box::new(f));
+    }
+
+    /// Makes the program check every value it outputs from now on against
+    /// `expected`, in order. When it is about to output something else, or
+    /// more values than there are in `expected`, it fails with
+    /// `RunError::UnexpectedOutput` before outputting anything, so the stack
+    /// pointer is left on the "output" instruction that went wrong.
+    ///
+    /// Like other errors, this makes the run methods that don't return errors
+    /// panic. Checking is off by default. `reset` and `soft_reset` start the
+    /// checks over from the first expected value.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, RunError};
+    ///
+    /// let mut program = Program::new(vec![104, 1, 104, 2, 104, 4, 99]);
+    /// program.set_expected_output(vec![1, 2, 3]);
+    /// assert_eq!(
+    ///     program.try_run(),
+    ///     Err(RunError::UnexpectedOutput { index: 2, value: 4, expected: Some(3) }),
+    /// );
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// ```
+    pub fn set_expected_output(&mut self, expected: Vec<Number>) {
+        self.expected_output = Some(expected);
+        self.expected_output_pos = 0;
+    }
+
+    fn check_output(&mut self, value: Number) -> Result<(), RunError> {
+        if let Some(expected) = &self.expected_output {
+            let index = self.expected_output_pos;
+            let expected = expected.get(index).copied();
+            if expected != Some(value) {
+                return Err(RunError::UnexpectedOutput { index, value, expected });
+            }
+            self.expected_output_pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Turns adding outputs to the output queue on or off. It is on by
+    /// default.
+    ///
+    /// With it turned off, the only way to get at outputs is through the
+    /// function set with `on_output`, and methods that look at the output
+    /// queue, such as `get_output` and `last_output`, won't see them.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::mpsc;
+    ///
+    /// let (tx, rx) = mpsc::channel();
+    /// let mut program = intcode::Program::new(vec![104, 1, 99]);
+    /// program.on_output(move |i| tx.send(i).unwrap());
+    /// program.set_queue_output(false);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// assert_eq!(rx.try_recv(), Ok(1));
+    /// assert_eq!(program.get_output(), None);
+    /// ```
+    pub fn set_queue_output(&mut self, on: bool) {
+        self.queue_output = on;
+    }
+
+    /// Makes `run_till_halted_or_blocked` and `run_or_panic` return right after
+    /// every output, or stops them from doing so. This is off by default.
+    ///
+    /// The program is still Running when it returns because of an output, and
+    /// continues where it left off when it is run again. Other methods that
+    /// run the program, such as `run` or `run_to_input`, are not affected.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.set_pause_on_output(true);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.pending_output(), &[1]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert!(program.halted());
+    /// ```
+    pub fn set_pause_on_output(&mut self, on: bool) {
+        self.pause_on_output = on;
+    }
+
+    fn push_output(&mut self, i: Number) -> Result<(), RunError> {
+        if let Some(writer) = self.output_writer.as_mut() {
+            writer.write(i).map_err(|e| RunError::OutputFailed(e.kind()))?;
+        }
+        self.log_event(Event::Output(i));
+        if let Some(hook) = self.output_hook.as_mut() {
+            hook(i);
+        }
+        if self.queue_output {
+            self.output.push(i);
+        }
+        Ok(())
+    }
+
+    fn get_input(&mut self) -> Number {
+        let result = self.input[self.input_pos];
+        self.input_pos += 1;
+        result
+    }
+
+    /// Return `true` if and only if this program's output queue is not empty.
+    pub fn has_output(&self) -> bool {
+        self.output_pos < self.output.len()
+    }
+
+    /// Returns the last output generated, even if it has already been consumed.
+    /// If no outputs have been generated yet, this will return `None`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![4, 3, 99, 1]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.get_output(), None);
+    /// assert_eq!(program.last_output(), Some(1));
+    /// ```
+    pub fn last_output(&self) -> Option<Number> {
+        if !self.output.is_empty() {
+            Some(self.output[self.output.len() - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Consumes and returns the first output in the output queue that has not
+    /// been consumed. If no outputs have been generated yet, this will return
+    /// `None`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![4, 5, 4, 6, 99, 1, 2]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.get_output(), Some(2));
+    /// assert_eq!(program.get_output(), None);
+    /// ```
+    pub fn get_output(&mut self) -> Option<Number> {
+        if self.has_output() {
+            self.output_pos += 1;
+            Some(self.output[self.output_pos - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the outputs that have not been consumed yet, oldest first,
+    /// without consuming them.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.pending_output(), &[2, 3]);
+    /// assert_eq!(program.get_output(), Some(2));
+    /// ```
+    pub fn pending_output(&self) -> &[Number] {
+        &self.output[self.output_pos..]
+    }
+
+    /// Consumes the unconsumed outputs in records of `chunk` outputs each,
+    /// oldest first, for programs whose output is a list of records such as
+    /// `(x, y, tile)` or `(address, x, y)`.
+    ///
+    /// If the number of unconsumed outputs isn't a multiple of `chunk`, the
+    /// incomplete record at the end is left unconsumed, so that the next call
+    /// returns it once the program has output the rest of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk` is 0.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 104, 4, 104, 5, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.drain_output_chunks(2), vec![vec![1, 2], vec![3, 4]]);
+    /// assert_eq!(program.pending_output(), &[5]);
+    /// ```
+    pub fn drain_output_chunks(&mut self, chunk: usize) -> Vec<Vec<Number>> {
+        assert!(chunk > 0, "chunk size must be at least 1");
+        let pending = self.output.len() - self.output_pos;
+        let end = self.output_pos + pending - pending % chunk;
+        let chunks = self.output[self.output_pos..end]
+            .chunks_exact(chunk)
+            .map(<[Number]>::to_vec)
+            .collect();
+        self.output_pos = end;
+        chunks
+    }
+
+    /// Un-consumes the most recently consumed output, so that the next call to
+    /// `get_output` returns it again. Does nothing if no outputs have been
+    /// consumed.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1));
+    /// program.unget_output();
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.get_output(), Some(2));
+    /// ```
+    pub fn unget_output(&mut self) {
+        self.output_pos = self.output_pos.saturating_sub(1);
+    }
+
+    /// Throws away all outputs, consumed or not, without touching memory or
+    /// execution. Marks returned by `output_mark` before this are no longer
+    /// valid afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 3, 100, 104, 2, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// program.clear_output();
+    ///
+    /// program.push_input(0);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(2));
+    /// ```
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+        self.output_pos = 0;
+        self.idle_mark.set(0);
+    }
+
+    /// Runs the program until it halts or needs input, consumes all of its
+    /// outputs, and checks that they are exactly `expected`. This is meant for
+    /// tests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the outputs differ from `expected`, with a message that lists
+    /// every position where they do. Also panics under the same conditions as
+    /// `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.assert_outputs(&[1, 2]);
+    /// ```
+    ///
+    /// ```should_panic
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// // Panics with "output 1 is 2, expected 3" and "output 2 is missing, expected 4".
+    /// program.assert_outputs(&[1, 3, 4]);
+    /// ```
+    pub fn assert_outputs(&mut self, expected: &[Number]) {
+        self.run_without_pausing();
+        let actual = self.pending_output().to_vec();
+        self.output_pos = self.output.len();
+
+        let describe = |val: Option<&Number>| match val {
+            Some(val) => format!("is {}", val),
+            None => "is missing".to_string(),
+        };
+        let differences: Vec<String> = (0..actual.len().max(expected.len()))
+            .filter(|&i| actual.get(i) != expected.get(i))
+            .map(|i| match expected.get(i) {
+                Some(val) => format!("output {} {}, expected {}", i, describe(actual.get(i)), val),
+                None => format!("output {} {}, expected nothing", i, describe(actual.get(i))),
+            })
+            .collect();
+        if !differences.is_empty() {
+            panic!("outputs differ from what was expected:\n{}", differences.join("\n"));
+        }
+    }
+
+    /// Returns the value at address 0, which is where programs that don't
+    /// output anything, such as the ones in day 2, leave their answer.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.result(), 3500);
+    /// ```
+    pub fn result(&self) -> Number {
+        self.get_mem(0)
+    }
+
+    /// Returns the value at address `pos`, without running anything. Memory
+    /// beyond the end of the tape reads as 0 until it is written.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 100, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.read_mem(1), 2);
+    /// assert_eq!(program.read_mem(100), 5);
+    /// assert_eq!(program.read_mem(1000), 0);
+    /// ```
+    pub fn read_mem(&self, pos: usize) -> Number {
+        self.get_mem(pos)
+    }
+
+    /// Returns a copy of the program's memory, from address 0 up to and
+    /// including the highest address that holds a value, with any gaps filled
+    /// with 0. Comparing two snapshots shows what a run changed.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 6, 99]);
+    /// let before = program.memory_snapshot();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(before, vec![1101, 2, 3, 6, 99]);
+    /// assert_eq!(program.memory_snapshot(), vec![1101, 2, 3, 6, 99, 0, 5]);
+    /// ```
+    pub fn memory_snapshot(&self) -> Vec<Number> {
+        self.effective_memory()
+    }
+
+    /// Writes `val` to address `pos`, for changing a program before running
+    /// it, like setting the noun and verb in day 2. Addresses beyond the end of
+    /// the tape can be written too, and memory grows to hold them.
+    ///
+    /// A function set with `on_write` is called just like for writes the
+    /// program makes, but watchpoints only stop on the program's own writes,
+    /// and the write doesn't count in `profile_report` or `distinct_writes`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1, 0, 0, 0, 99, 30, 40, 50]);
+    /// program.write_mem(1, 5);
+    /// program.write_mem(2, 6);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.result(), 70);
+    /// ```
+    pub fn write_mem(&mut self, pos: usize, val: Number) {
+        self.set_mem(pos, val);
+        self.watch_hit = None;
+    }
+
+    /// Returns the number of outputs the program has produced so far, to be
+    /// passed to `outputs_since` later.
+    pub fn output_mark(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Returns the outputs produced since `output_mark` returned `mark`,
+    /// whether they have been consumed or not. This does not consume them.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 3, 100, 104, 2, 104, 3, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// let mark = program.output_mark();
+    /// program.push_input(0);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.outputs_since(mark), &[2, 3]);
+    /// assert_eq!(program.get_output(), Some(1));
+    /// ```
+    pub fn outputs_since(&self, mark: usize) -> &[Number] {
+        &self.output[mark.min(self.output.len())..]
+    }
+
+    /// Returns how many instructions the program has executed since it was
+    /// created or last reset. Every instruction counts once, including jumps
+    /// and opcodes added with `register_opcode`, which makes this a deterministic
+    /// way to compare how much work two programs do.
+    ///
+    /// # Example
+    /// ```
+    /// // A jump to 3, and a halt.
+    /// let mut program = intcode::Program::new(vec![1105, 1, 3, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.step_count(), 2);
+    ///
+    /// program.reset();
+    /// assert_eq!(program.step_count(), 0);
+    /// ```
+    pub fn step_count(&self) -> usize {
+        self.counters.steps as usize
+    }
+
+    /// Returns how many times the relative base has been adjusted, that is, how
+    /// many times the "relative base offset" opcode has been executed.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![109, 5, 109, -5, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.relative_base_changes(), 2);
+    /// ```
+    pub fn relative_base_changes(&self) -> u64 {
+        self.counters.relative_base_changes
+    }
+
+    /// Returns the number of different addresses the program has written to,
+    /// whether it wrote a new value or the one that was already there.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 1, 1, 100, 1101, 2, 2, 100, 1101, 0, 0, 0, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.distinct_writes(), 2);
+    /// ```
+    pub fn distinct_writes(&self) -> usize {
+        self.counters.distinct_writes
+    }
+
+    /// Returns statistics about how the program has executed so far.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 7, 4, 7, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// let report = program.profile_report();
+    /// assert_eq!(report.instructions, 3);
+    /// assert_eq!(report.opcode_counts[0], ("ADD", 1));
+    /// assert_eq!(report.memory_reads, 8);
+    /// assert_eq!(report.memory_writes, 1);
+    /// assert_eq!(report.high_water_mark, 7);
+    /// ```
+    pub fn profile_report(&self) -> ProfilingReport {
+        ProfilingReport {
+            instructions: self.counters.steps,
+            opcode_counts: Opcode::ALL
+                .iter()
+                .map(|opcode| (opcode.mnemonic(), self.counters.opcodes[*opcode as usize]))
+                .collect(),
+            memory_reads: self.counters.reads.get(),
+            memory_writes: self.counters.writes,
+            relative_base_changes: self.counters.relative_base_changes,
+            high_water_mark: self.counters.high_water_mark.get(),
+        }
+    }
+
+    /// Starts recording the address of every instruction the program executes,
+    /// keeping only the most recent `capacity` of them. Any addresses recorded
+    /// before are discarded. A capacity of 0 turns recording off again.
+    ///
+    /// An instruction that fails with an error is recorded too, but an input
+    /// instruction that has to wait for input is only recorded once it reads.
+    ///
+    /// Recording is off by default.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// program.enable_pc_history(2);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.recent_pcs(), &[4, 6]);
+    /// ```
+    pub fn enable_pc_history(&mut self, capacity: usize) {
+        self.pc_history = Vec::with_capacity(2 * capacity);
+        self.pc_history_capacity = capacity;
+    }
+
+    /// Returns the addresses of the most recently executed instructions, oldest
+    /// first, if recording was turned on with `enable_pc_history`.
+    pub fn recent_pcs(&self) -> &[usize] {
+        let start = self.pc_history.len().saturating_sub(self.pc_history_capacity);
+        &self.pc_history[start..]
+    }
+
+    fn record_pc(&mut self) {
+        // Let the history grow to twice its capacity before dropping the
+        // oldest half, so it can always be returned as a single slice.
+        if self.pc_history.len() == 2 * self.pc_history_capacity {
+            self.pc_history.drain(..self.pc_history_capacity);
+        }
+        self.pc_history.push(self.sp);
+    }
+
+    /// Removes the most recently recorded address. This is for an instruction
+    /// that was recorded but turned out to be waiting for input.
+    fn unrecord_pc(&mut self) {
+        if self.pc_history_capacity > 0 {
+            self.pc_history.pop();
+        }
+    }
+
+    /// Starts recording everything the program does in an event log, which can
+    /// be looked at afterwards with `event_log`. Any events recorded before
+    /// are discarded.
+    ///
+    /// Every executed instruction is recorded, followed by the value it read
+    /// or output, if any, and an `Event::Halt` when the program halts. An
+    /// "input" instruction that has to wait for input is only recorded once
+    /// it actually reads, and an instruction that fails with an error isn't
+    /// recorded at all. The log keeps growing until it is turned off with
+    /// `disable_event_log`, so it can take up a lot of memory for programs
+    /// that run for a long time.
+    ///
+    /// Recording is off by default.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{opcode, Event, Program};
+    ///
+    /// let mut program = Program::new(vec![3, 100, 4, 100, 99]);
+    /// program.enable_event_log();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// program.push_input(7);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.event_log(), &[
+    ///     Event::Exec { addr: 0, opcode: opcode::INPUT },
+    ///     Event::Input(7),
+    ///     Event::Exec { addr: 2, opcode: opcode::OUTPUT },
+    ///     Event::Output(7),
+    ///     Event::Exec { addr: 4, opcode: opcode::HALT },
+    ///     Event::Halt,
+    /// ]);
+    /// ```
+    pub fn enable_event_log(&mut self) {
+        self.event_log = Some(Vec::new());
+    }
+
+    /// Stops recording events, and discards the ones recorded so far.
+    pub fn disable_event_log(&mut self) {
+        self.event_log = None;
+    }
+
+    /// Returns the events recorded since `enable_event_log` was called, oldest
+    /// first. This is empty if recording is off.
+    pub fn event_log(&self) -> &[Event] {
+        self.event_log.as_deref().unwrap_or(&[])
+    }
+
+    fn log_event(&mut self, event: Event) {
+        if let Some(log) = self.event_log.as_mut() {
+            log.push(event);
+        }
+    }
+
+    /// Removes the most recently logged event. This is for an instruction
+    /// that was logged but turned out not to execute.
+    fn unlog_event(&mut self) {
+        if let Some(log) = self.event_log.as_mut() {
+            log.pop();
+        }
+    }
+
+    fn call_trace(&mut self, instruction: &Instruction) {
+        let name = instruction.opcode.mnemonic();
+        if let Some(Trace { opcodes: Some(opcodes), .. }) = &self.trace {
+            if !opcodes.contains(name) {
+                return;
+            }
+        }
+        let line = self.format_instruction(self.sp).unwrap();
+        if let Some(trace) = self.trace.as_mut() {
+            (trace.f)(self.sp, &line);
+        }
+    }
+
+    /// Moves the stack pointer past the instruction that was just executed,
+    /// which had the given opcode.
+    fn increase_sp(&mut self, opcode: Opcode) {
+        self.sp += match opcode {
+            Opcode::Halt => 0,
+            opcode => opcode.width(),
+        }
+    }
+
+    /// Decodes the instruction at `addr`. Returns `None` if the value there
+    /// isn't an instruction.
+    fn decode_at(&self, addr: usize) -> Option<DecodedInstruction> {
+        let raw = self.get_mem(addr);
+        let instruction = Instruction::decode(raw)?;
+        let modes = [instruction.param1, instruction.param2, instruction.param3];
+        let params = (1..instruction.opcode.width())
+            .map(|n| (modes[n - 1], self.get_mem(addr + n)))
+            .collect();
+        Some(DecodedInstruction {
+            addr,
+            opcode: raw % 100,
+            mnemonic: instruction.opcode.mnemonic(),
+            params,
+        })
+    }
+
+    /// Renders the instruction at `addr` as assembly, the way
+    /// `DecodedInstruction` displays. Returns `None` if the value at `addr`
+    /// isn't an instruction.
+    fn format_instruction(&self, addr: usize) -> Option<String> {
+        self.decode_at(addr).map(|instruction| instruction.to_string())
+    }
+
+    /// Decodes the instruction the program will execute next, without
+    /// executing it. Returns `None` if the value at the stack pointer isn't an
+    /// instruction.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{opcode, ParameterMode, Program};
+    ///
+    /// let program = Program::new(vec![1001, 4, 3, 0, 99]);
+    /// let instruction = program.current_instruction().unwrap();
+    /// assert_eq!(instruction.opcode, opcode::ADD);
+    /// assert_eq!(instruction.params[1], (ParameterMode::Immediate, 3));
+    /// assert_eq!(instruction.to_string(), "ADD @4 #3 -> @0");
+    /// ```
+    pub fn current_instruction(&self) -> Option<DecodedInstruction> {
+        self.decode_at(self.sp)
+    }
+
+    /// Executes a single instruction, and returns the state the program is in
+    /// afterwards. This is for going through a program one instruction at a
+    /// time, for example to show its memory after every instruction.
+    ///
+    /// Nothing is executed if the program has halted, or if it needs input
+    /// while there is none, so this is safe to call again and again: it just
+    /// returns the same state, and the stack pointer stays where it is. Use
+    /// `step_verbose` to also see which instruction was executed.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, State};
+    ///
+    /// let mut program = Program::new(vec![104, 7, 3, 100, 99]);
+    /// assert_eq!(program.step(), State::Running);
+    /// assert_eq!(program.step(), State::WaitingForInput);
+    /// assert_eq!(program.step(), State::WaitingForInput);
+    ///
+    /// program.push_input(1);
+    /// assert_eq!(program.step(), State::Running);
+    /// assert_eq!(program.step(), State::Halted);
+    /// assert_eq!(program.step(), State::Halted);
+    /// ```
+    pub fn step(&mut self) -> State {
+        if !self.halted_or_blocked() {
+            self.execute_instruction();
+        }
+        self.state
+    }
+
+    /// Executes a single instruction, and returns the state the program is in
+    /// afterwards along with the instruction that was executed.
+    ///
+    /// Nothing is executed if the program has halted, or if it needs input
+    /// while there is none. The instruction returned is `None` then, and the
+    /// stack pointer stays where it is. It is also `None` if the program
+    /// halted instead of executing the instruction, like an "input" does when
+    /// there is no input under `InputPolicy::Halt`, or one that fails does
+    /// when an error handler is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, State};
+    ///
+    /// let mut program = Program::new(vec![104, 7, 3, 100, 99]);
+    /// let (state, instruction) = program.step_verbose();
+    /// assert_eq!(state, State::Running);
+    /// assert_eq!(instruction.unwrap().to_string(), "OUT #7");
+    ///
+    /// assert_eq!(program.step_verbose(), (State::WaitingForInput, None));
+    /// assert_eq!(program.step_verbose(), (State::WaitingForInput, None));
+    /// ```
+    pub fn step_verbose(&mut self) -> (State, Option<DecodedInstruction>) {
+        if self.halted_or_blocked() {
+            return (self.state, None);
+        }
+        let steps = self.counters.steps;
+        let instruction = self.current_instruction();
+        self.execute_instruction();
+        if self.counters.steps == steps {
+            // The instruction stopped the program without being executed.
+            return (self.state, None);
+        }
+        (self.state, instruction)
+    }
+
+    fn disassembly(&self) -> Disassembly<'_> {
+        Disassembly {
+            program: self,
+            addr: 0,
+        }
+    }
+
+    /// Renders the program's memory as assembly, one instruction per line,
+    /// from address 0 up to the first "halt" instruction or the end of the
+    /// tape. Each line starts with the instruction's address, followed by the
+    /// instruction the way `DecodedInstruction` displays, so `@`, `#` and `~`
+    /// mark parameters in position, immediate and relative mode.
+    ///
+    /// A value that isn't an instruction is shown as `DATA` and skipped. An
+    /// instruction whose parameters don't all fit on the tape is shown with
+    /// the parameters that do, and a note saying it is truncated. As with
+    /// [`output_instruction_addresses`](Program::output_instruction_addresses),
+    /// the result is a best guess.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![1001, 9, 3, 9, 42, 204, -1, 99, 7]);
+    /// assert_eq!(program.disassemble(), vec![
+    ///     "0: ADD @9 #3 -> @9",
+    ///     "4: DATA 42",
+    ///     "5: OUT ~-1",
+    ///     "7: HALT",
+    /// ]);
+    ///
+    /// let program = intcode::Program::new(vec![104, 1, 1101, 2]);
+    /// assert_eq!(program.disassemble(), vec![
+    ///     "0: OUT #1",
+    ///     "2: ADD #2 ; truncated: the tape ends before the instruction does",
+    /// ]);
+    /// ```
+    pub fn disassemble(&self) -> Vec<String> {
+        let len = self.program.len();
+        let mut lines = Vec::new();
+        for (addr, instruction) in self.disassembly() {
+            let Some(instruction) = instruction else {
+                lines.push(format!("{}: DATA {}", addr, self.get_mem(addr)));
+                continue;
+            };
+            let mut decoded = self.decode_at(addr).unwrap();
+            if addr + instruction.opcode.width() > len {
+                decoded.params.truncate(len - addr - 1);
+                lines.push(format!("{}: {} ; truncated: the tape ends before the instruction does", addr, decoded));
+                break;
+            }
+            lines.push(format!("{}: {}", addr, decoded));
+            if instruction.opcode == Opcode::Halt {
+                break;
+            }
+        }
+        lines
+    }
+
+    /// Returns the addresses of all "output" instructions in the program.
+    ///
+    /// The tape is decoded front to back, one instruction after the other, so
+    /// this is a best guess, because code and data can be mixed: data that
+    /// happens to look like an instruction is decoded as one, and instructions
+    /// that the program writes while it runs can't be found.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![104, 1, 3, 0, 4, 0, 99]);
+    /// assert_eq!(program.output_instruction_addresses(), vec![0, 4]);
+    /// ```
+    pub fn output_instruction_addresses(&self) -> Vec<usize> {
+        self.disassembly()
+            .filter(|(_, i)| matches!(i, Some(Instruction { opcode: Opcode::Output, .. })))
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    /// Returns how many instructions the tape starts with, as a rough measure
+    /// of the size of the program.
+    ///
+    /// The tape is decoded front to back from address 0, one instruction
+    /// after the other, up to the first value that isn't an instruction, or
+    /// the first instruction whose parameters don't fit on the tape. For why
+    /// this is only an estimate, see
+    /// [`output_instruction_addresses`](Program::output_instruction_addresses).
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![1101, 1, 2, 0, 104, 7, 99, -5, 104]);
+    /// assert_eq!(program.static_instruction_count(), 3);
+    /// ```
+    pub fn static_instruction_count(&self) -> usize {
+        let len = self.program.len();
+        self.disassembly()
+            .take_while(|(addr, i)| i.as_ref().is_some_and(|i| addr + i.opcode.width() <= len))
+            .count()
+    }
+
+    /// Returns `true` if the program is a quine: run from the start without
+    /// any input, it outputs an exact copy of its initial tape and halts.
+    ///
+    /// This runs a fresh copy of the program, so this one isn't affected. To
+    /// avoid running forever, the copy gets up to `QUINE_STEP_BUDGET`
+    /// instructions to halt in. A program that takes longer, needs input, or
+    /// fails is not considered a quine.
+    ///
+    /// # Example
+    /// ```
+    /// let tape = vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+    /// assert!(intcode::Program::new(tape).is_quine());
+    /// assert!(!intcode::Program::new(vec![104, 104, 99]).is_quine());
+    /// ```
+    pub fn is_quine(&self) -> bool {
+        let mut copy = Program::new(&self.initial[..]);
+        for _ in 0..QUINE_STEP_BUDGET {
+            if copy.halted_or_blocked() {
+                break;
+            }
+            if Instruction::decode(copy.get_mem(copy.sp)).is_none() || copy.try_execute_instruction().is_err() {
+                return false;
+            }
+        }
+        copy.halted() && copy.output == *self.initial
+    }
+
+    /// Makes a best guess at whether the program can ever reach a "halt"
+    /// instruction, without running it.
+    ///
+    /// Starting at address 0, this follows every path through the program:
+    /// both ways out of a conditional jump, or just one if the condition is an
+    /// immediate value. Paths that run into something that isn't a valid
+    /// instruction are dropped, because the program would fail there.
+    ///
+    /// This assumes the program never changes its own code, which plenty of
+    /// programs do, and it gives up on jumps whose target isn't an immediate
+    /// value, since it can't know where those go. When it gives up, it returns
+    /// `true`, so `false` means no halt instruction was found on any path,
+    /// while `true` only means one might be reached.
+    ///
+    /// # Example
+    /// ```
+    /// // Jumps over an instruction that would fail, into the halt.
+    /// let program = intcode::Program::new(vec![1105, 1, 4, 0, 99]);
+    /// assert!(program.reaches_halt());
+    ///
+    /// // Jumps back to itself forever.
+    /// let program = intcode::Program::new(vec![1105, 1, 0, 99]);
+    /// assert!(!program.reaches_halt());
+    /// ```
+    pub fn reaches_halt(&self) -> bool {
+        let mut visited = HashSet::new();
+        let mut todo = vec![0];
+
+        while let Some(addr) = todo.pop() {
+            if !visited.insert(addr) {
+                continue;
+            }
+            let instruction = match Instruction::decode(self.get_mem(addr)) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            let next = addr + instruction.opcode.width();
+
+            match instruction.opcode {
+                Opcode::Halt => return true,
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    let target = match instruction.param2 {
+                        ParameterMode::Immediate => self.get_mem(addr + 2),
+                        _ => return true,
+                    };
+                    let taken = match instruction.param1 {
+                        ParameterMode::Immediate => {
+                            let condition = self.get_mem(addr + 1) != 0;
+                            Some(condition == matches!(instruction.opcode, Opcode::JumpIfTrue))
+                        }
+                        _ => None,
+                    };
+                    if taken != Some(false) {
+                        if let Ok(target) = usize::try_from(target) {
+                            todo.push(target);
+                        }
+                    }
+                    if taken != Some(true) {
+                        todo.push(next);
+                    }
+                }
+                _ => todo.push(next),
+            }
+        }
+
+        false
+    }
+
+    fn param(&self, param: usize) -> Result<Number, RunError> {
+        let value = self.read(self.sp + param)?;
+
+        match self.param_mode(param)? {
+            ParameterMode::Position => { self.read(Program::address(value)?) },
+            ParameterMode::Immediate => { Ok(value) },
+            ParameterMode::Relative => { self.read(self.relative_address(value)?) },
+        }
+    }
+
+    /// Returns the mode of a parameter of the instruction at the stack pointer,
+    /// failing the way `try_run` does if that isn't a valid instruction.
+    fn param_mode(&self, param: usize) -> Result<ParameterMode, RunError> {
+        let instruction = self.decode_current()?;
+        Ok(match param {
+            1 => instruction.param1,
+            2 => instruction.param2,
+            3 => instruction.param3,
+            _ => unreachable!()
+        })
+    }
+
+    /// Decodes the instruction at the stack pointer, returning the error
+    /// `instruction_problem` finds if it isn't a valid instruction.
+    fn decode_current(&self) -> Result<Instruction, RunError> {
+        let raw = self.get_mem(self.sp);
+        Instruction::decode(raw).ok_or_else(|| {
+            self.instruction_problem(raw)
+                .unwrap_or(RunError::UnknownOpcode { sp: self.sp, opcode: raw % 100 })
+        })
+    }
+
+    /// Converts a value to an address, failing if it is negative or doesn't
+    /// fit in a `usize`.
+    fn address(value: Number) -> Result<usize, RunError> {
+        if value < 0 {
+            return Err(RunError::NegativeAddress(value));
+        }
+        usize::try_from(value).map_err(|_| RunError::AddressOverflow)
+    }
+
+    /// Converts a parameter in relative mode to the address it refers to.
+    fn relative_address(&self, value: Number) -> Result<usize, RunError> {
+        let addr = self.relative_base.checked_add(value).ok_or(RunError::AddressOverflow)?;
+        Program::address(addr)
+    }
+
+    /// Returns a position to write to or read from, taking into account the
+    /// parameter mode. The number passed in is the parameter that needs to be
     /// converted into the appropriate position (so 3 for opcode 1, etc).
-    fn get_pos(&self, param: usize) -> usize {
-        let instruction = Instruction::from(self.get_mem(self.sp));
+    fn get_pos(&self, param: usize) -> Result<usize, RunError> {
+        let pos = self.read(self.sp + param)?;
+
+        match self.param_mode(param)? {
+            ParameterMode::Position => { Program::address(pos) },
+            ParameterMode::Immediate => { Err(RunError::WriteToImmediate { sp: self.sp }) },
+            ParameterMode::Relative => { self.relative_address(pos) },
+        }
+    }
+
+    /// Reads memory on behalf of the running program. This is `get_mem`, except
+    /// that with strict reads turned on, reading memory that was never written
+    /// is an error.
+    fn read(&self, pos: usize) -> Result<Number, RunError> {
+        if self.strict_reads && pos >= self.program.len() && self.get_extra(pos).is_none() {
+            return Err(RunError::UninitializedRead(pos));
+        }
+        self.counters.reads.set(self.counters.reads.get() + 1);
+        self.counters.high_water_mark.set(self.counters.high_water_mark.get().max(pos));
+        Ok(self.get_mem(pos))
+    }
+
+    /// Writes memory on behalf of the running program.
+    fn write(&mut self, pos: usize, val: Number) {
+        self.set_mem(pos, val);
+
+        let dense_end = self.program.len().max(self.extra.end());
+        let counters = &mut self.counters;
+        counters.writes += 1;
+        counters.high_water_mark.set(counters.high_water_mark.get().max(pos));
+
+        if pos >= counters.written.len() && pos < dense_end {
+            counters.written.resize(dense_end, false);
+            let rest = counters.written_sparse.split_off(&dense_end);
+            for moved in std::mem::replace(&mut counters.written_sparse, rest) {
+                counters.written[moved] = true;
+            }
+        }
+        let first_write = match counters.written.get_mut(pos) {
+            Some(written) => !std::mem::replace(written, true),
+            None => counters.written_sparse.insert(pos),
+        };
+        if first_write {
+            counters.distinct_writes += 1;
+        }
+    }
+
+    fn get_mem(&self, pos: usize) -> Number {
+        if !self.overlay.is_empty() {
+            if let Some(&val) = self.overlay.get(&pos) {
+                return val;
+            }
+        }
+        if pos < self.program.len() {
+            self.program[pos]
+        } else {
+            self.extra.get(pos).unwrap_or(0)
+        }
+    }
+
+    /// Returns the value at an address beyond the end of the tape, or `None`
+    /// if it was never written.
+    fn get_extra(&self, pos: usize) -> Option<Number> {
+        self.overlay.get(&pos).copied().or_else(|| self.extra.get(pos))
+    }
+
+    fn set_extra(&mut self, pos: usize, val: Number) {
+        self.store(pos, val);
+    }
+
+    /// Puts a value in memory. Memory that is shared with another program is
+    /// left alone, and the value goes in the overlay instead, until the
+    /// overlay is large enough that copying it all is worth it.
+    fn store(&mut self, pos: usize, val: Number) {
+        if self.overlay.is_empty() {
+            if pos < self.program.len() {
+                if !self.shares_tape() {
+                    Arc::make_mut(&mut self.program)[pos] = val;
+                    return;
+                }
+            } else if let Some(extra) = Arc::get_mut(&mut self.extra) {
+                extra.set(pos, val);
+                return;
+            }
+        }
+        self.overlay.insert(pos, val);
+        let shared = self.program.len() + self.extra.cells.len() + self.extra.sparse.len();
+        if self.overlay.len() > OVERLAY_LIMIT.max(shared / 16) {
+            self.fold_overlay();
+        }
+    }
+
+    /// Returns whether the tape is shared with another program. Sharing it
+    /// with the copy kept for `reset` doesn't count, since that is never
+    /// written to.
+    fn shares_tape(&self) -> bool {
+        let own = if Arc::ptr_eq(&self.program, &self.initial) { 2 } else { 1 };
+        Arc::strong_count(&self.program) > own
+    }
+
+    /// Moves the cells in the overlay into memory, copying the memory first if
+    /// it is shared.
+    fn fold_overlay(&mut self) {
+        let mut tape_cells = std::mem::take(&mut self.overlay);
+        let extra_cells = tape_cells.split_off(&self.program.len());
+        if !tape_cells.is_empty() {
+            let tape = Arc::make_mut(&mut self.program);
+            for (pos, val) in tape_cells {
+                tape[pos] = val;
+            }
+        }
+        if !extra_cells.is_empty() {
+            let extra = Arc::make_mut(&mut self.extra);
+            for (pos, val) in extra_cells {
+                extra.set(pos, val);
+            }
+        }
+    }
+
+    /// Returns the tape as it is now, which is only copied if part of it is in
+    /// the overlay.
+    fn tape(&self) -> Cow<'_, [Number]> {
+        let len = self.program.len();
+        if self.overlay.range(..len).next().is_none() {
+            return Cow::Borrowed(&self.program[..]);
+        }
+        let mut tape = self.program.to_vec();
+        for (&pos, &val) in self.overlay.range(..len) {
+            tape[pos] = val;
+        }
+        Cow::Owned(tape)
+    }
+
+    /// Iterates over the cells beyond the end of the tape that have been
+    /// written, in order of address.
+    fn extra_cells(&self) -> impl Iterator<Item = (usize, Number)> + '_ {
+        let len = self.program.len();
+        let mut extra = self.extra.iter().filter(move |&(pos, _)| pos >= len).peekable();
+        let mut overlay = self.overlay.range(len..).map(|(&pos, &val)| (pos, val)).peekable();
+        // Merge the two, with the overlay taking the place of cells in both.
+        std::iter::from_fn(move || match (extra.peek(), overlay.peek()) {
+            (Some(&(pos, _)), Some(&(over, _))) if pos < over => extra.next(),
+            (Some(&(pos, _)), Some(&(over, _))) => {
+                if pos == over {
+                    extra.next();
+                }
+                overlay.next()
+            }
+            (Some(_), None) => extra.next(),
+            (None, _) => overlay.next(),
+        })
+    }
+
+    fn set_mem(&mut self, pos: usize, val: Number) {
+        let watched = self.watchpoints.contains(&pos);
+        let old = if self.write_hook.is_some() || watched {
+            self.get_mem(pos)
+        } else {
+            0
+        };
+        if watched {
+            self.watch_hit = Some((pos, old, val));
+        }
+        self.store(pos, val);
+        if let Some(hook) = self.write_hook.as_mut() {
+            hook(pos, old, val);
+        }
+    }
+
+    /// Frees memory used to store cells beyond the end of the tape that hold 0,
+    /// which they would read as anyway. This makes no difference to how the
+    /// program runs, except that with strict reads turned on these cells count
+    /// as never written again.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 0, 0, 100, 4, 100, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// program.compact_memory();
+    /// assert_eq!(program.get_output(), Some(0));
+    /// ```
+    pub fn compact_memory(&mut self) {
+        self.fold_overlay();
+        let len = self.program.len();
+        let extra = Arc::make_mut(&mut self.extra);
+        let start = extra.start;
+        for (i, cell) in extra.cells.iter_mut().enumerate() {
+            if start + i < len || *cell == Some(0) {
+                *cell = None;
+            }
+        }
+        extra.sparse.retain(|&pos, &mut val| pos >= len && val != 0);
+        while extra.cells.last() == Some(&None) {
+            extra.cells.pop();
+        }
+        extra.cells.shrink_to_fit();
+    }
+
+    /// Returns a dense copy of memory, from address 0 up to and including the
+    /// highest address that holds a value, with any gaps filled with 0.
+    fn effective_memory(&self) -> Vec<Number> {
+        let mut memory = self.tape().into_owned();
+        if let Some((last, _)) = self.extra_cells().last() {
+            memory.resize(last + 1, 0);
+        }
+        for (pos, val) in self.extra_cells() {
+            memory[pos] = val;
+        }
+        memory
+    }
+
+    /// Returns a dump of the program's memory, with addresses and values
+    /// written in the given radix, which must be 2, 8, 10 or 16.
+    ///
+    /// Memory is dumped from address 0 up to the highest address that holds a
+    /// value, eight cells per line, each line starting with the address of its
+    /// first cell. Values are separated by commas, so in radix 10 they read
+    /// exactly like the program's tape.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the radix is not one of 2, 8, 10 or 16.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![1101, -1, 10, 0, 4, 0, 99, 0, 255]);
+    /// assert_eq!(
+    ///     program.dump_memory_radix(16),
+    ///     "0: 44d,-1,a,0,4,0,63,0\n8: ff\n",
+    /// );
+    /// ```
+    pub fn dump_memory_radix(&self, radix: u32) -> String {
+        fn format(val: u64, radix: u32) -> String {
+            match radix {
+                2 => format!("{:b}", val),
+                8 => format!("{:o}", val),
+                10 => format!("{}", val),
+                16 => format!("{:x}", val),
+                _ => unreachable!(),
+            }
+        }
+
+        assert!(
+            matches!(radix, 2 | 8 | 10 | 16),
+            "Unsupported radix for a memory dump: {}",
+            radix,
+        );
+        let memory = self.effective_memory();
+        let width = format(memory.len().saturating_sub(1) as u64, radix).len();
+
+        let mut dump = String::new();
+        for (row, cells) in memory.chunks(8).enumerate() {
+            let values: Vec<_> = cells
+                .iter()
+                .map(|&val| {
+                    let sign = if val < 0 { "-" } else { "" };
+                    format!("{}{}", sign, format(val.unsigned_abs(), radix))
+                })
+                .collect();
+            let addr = format((row * 8) as u64, radix);
+            dump.push_str(&format!("{:>width$}: {}\n", addr, values.join(","), width = width));
+        }
+        dump
+    }
+
+    /// Returns every memory cell whose value differs from the initial tape, as
+    /// (address, current value) pairs sorted by address. Addresses beyond the
+    /// end of the initial tape count as having started out as 0.
+    ///
+    /// Writing these values into the initial tape, extending it with 0s where
+    /// needed, gives the program's current memory.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 7, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.minimal_patch(), vec![(7, 5)]);
+    /// ```
+    pub fn minimal_patch(&self) -> Vec<(usize, Number)> {
+        self.modified_cells().collect()
+    }
+
+    /// Iterates over every memory cell whose value differs from the initial
+    /// tape, in the same way and order as `minimal_patch`, but without
+    /// collecting them.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 7, 1101, 0, 1, 100, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// let sum: i64 = program.modified_cells().map(|(_, val)| val).sum();
+    /// assert_eq!(sum, 6);
+    /// ```
+    pub fn modified_cells(&self) -> impl Iterator<Item = (usize, Number)> + '_ {
+        // Until the program makes its own copy of the tape, it is the same
+        // vector as the initial tape, so only the overlay needs comparing.
+        let len = self.program.len();
+        let unchanged = Arc::ptr_eq(&self.program, &self.initial);
+        let overlay = self.overlay.range(..if unchanged { len } else { 0 });
+        (0..if unchanged { 0 } else { len })
+            .map(|pos| (pos, self.get_mem(pos)))
+            .chain(overlay.map(|(&pos, &val)| (pos, val)))
+            .filter(|&(pos, val)| val != self.initial[pos])
+            .chain(self.extra_cells().filter(|&(_, val)| val != 0))
+    }
+
+    fn execute_instruction(&mut self) {
+        if let Err(e) = self.try_execute_instruction() {
+            self.handle_error(e);
+        }
+    }
+
+    /// Calls the function given to `set_error_handler` with `e` and halts the
+    /// program, or panics if there is no such function.
+    fn handle_error(&mut self, e: RunError) {
+        match self.error_handler.as_mut() {
+            Some(handler) => {
+                handler(&e.to_string());
+                self.state = State::Halted;
+            }
+            None => panic!("{}", e),
+        }
+    }
+
+    /// Returns the error there is with the instruction `raw` at the stack
+    /// pointer itself, if any, before anything is executed.
+    fn instruction_problem(&self, raw: Number) -> Option<RunError> {
+        let sp = self.sp;
+        let opcode = match Opcode::decode(raw % 100) {
+            Some(opcode) => opcode,
+            None if self.custom_opcodes.contains_key(&(raw % 100)) => return None,
+            None => return Some(RunError::UnknownOpcode { sp, opcode: raw % 100 }),
+        };
+        for mode in [raw / 100 % 10, raw / 1000 % 10, raw / 10000 % 10] {
+            if ParameterMode::decode(mode).is_none() {
+                return Some(RunError::UnknownParameterMode { sp, mode });
+            }
+        }
+        let target_mode = match opcode {
+            Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => raw / 10000 % 10,
+            Opcode::Input => raw / 100 % 10,
+            _ => return None,
+        };
+        match ParameterMode::decode(target_mode) {
+            Some(ParameterMode::Immediate) => Some(RunError::WriteToImmediate { sp }),
+            _ => None,
+        }
+    }
+
+    fn try_execute_instruction(&mut self) -> Result<(), RunError> {
+        if let State::Halted = self.state {
+            return Err(RunError::Halted);
+        }
+        let raw = self.read(self.sp)?;
+        if !self.custom_opcodes.is_empty() && Opcode::decode(raw % 100).is_none() {
+            if let Some((width, mut handler)) = self.custom_opcodes.remove(&(raw % 100)) {
+                self.execute_custom(raw % 100, width, &mut handler);
+                self.custom_opcodes.insert(raw % 100, (width, handler));
+                return Ok(());
+            }
+        }
+        if self.pc_history_capacity > 0 {
+            self.record_pc();
+        }
+        if let Some(e) = self.instruction_problem(raw) {
+            return Err(e);
+        }
+        let instruction = self.decode_current()?;
+
+        // Input is traced once it is known not to block, so that it is only
+        // traced once however long it waits.
+        if self.trace.is_some() && instruction.opcode != Opcode::Input {
+            self.call_trace(&instruction);
+        }
+        // Anything logged for an instruction that fails is taken out again.
+        let logged = self.event_log.as_ref().map(Vec::len);
+        if self.event_log.is_some() {
+            let opcode = self.get_mem(self.sp) % 100;
+            self.log_event(Event::Exec { addr: self.sp, opcode });
+        }
+
+        let result = self.execute_opcode(&instruction);
+        if let (Err(_), Some(log), Some(len)) = (&result, self.event_log.as_mut(), logged) {
+            log.truncate(len);
+        }
+        let Some(bump_sp) = result? else {
+            return Ok(());
+        };
+        self.counters.steps += 1;
+        self.counters.opcodes[instruction.opcode as usize] += 1;
+        if bump_sp {
+            self.increase_sp(instruction.opcode);
+        }
+        Ok(())
+    }
+
+    /// Carries out a decoded instruction. Returns whether the stack pointer
+    /// should be moved past it, or `None` if the instruction didn't execute
+    /// after all because it is waiting for input or halted instead.
+    fn execute_opcode(&mut self, instruction: &Instruction) -> Result<Option<bool>, RunError> {
+        let mut bump_sp = true;
+        match instruction.opcode {
+            Opcode::Add => {
+                let pos = self.get_pos(3)?;
+                let val = self.param(1)?.checked_add(self.param(2)?);
+                self.write(pos, val.ok_or(RunError::ArithmeticOverflow { sp: self.sp })?);
+            }
+            Opcode::Multiply => {
+                let pos = self.get_pos(3)?;
+                let val = self.param(1)?.checked_mul(self.param(2)?);
+                self.write(pos, val.ok_or(RunError::ArithmeticOverflow { sp: self.sp })?);
+            }
+            Opcode::Input => {
+                self.read_sentinel = false;
+                if self.input.len() == self.input_pos {
+                    self.read_input_line()?;
+                }
+                let queued = self.input.len() > self.input_pos;
+                let input = if queued {
+                    self.input[self.input_pos]
+                } else {
+                    match self.input_policy {
+                        InputPolicy::Block if !self.input_closed => {
+                            self.unrecord_pc();
+                            self.unlog_event();
+                            self.state = State::WaitingForInput;
+                            return Ok(None);
+                        }
+                        InputPolicy::Error => return Err(RunError::InputExhausted),
+                        InputPolicy::Value(v) => {
+                            self.read_sentinel = true;
+                            v
+                        }
+                        InputPolicy::Block | InputPolicy::Halt => {
+                            self.unlog_event();
+                            self.log_event(Event::Halt);
+                            self.state = State::Halted;
+                            return Ok(None);
+                        }
+                    }
+                };
+                if self.trace.is_some() {
+                    self.call_trace(instruction);
+                }
+                // The input is only taken once the address is known to be
+                // valid, so that it is still there if the instruction fails.
+                let pos = self.get_pos(1)?;
+                if queued {
+                    self.input_pos += 1;
+                }
+                self.log_event(Event::Input(input));
+                self.write(pos, input);
+            }
+            Opcode::Output => {
+                let value = self.param(1)?;
+                self.check_output(value)?;
+                self.push_output(value)?;
+            }
+            Opcode::JumpIfTrue => {
+                if self.param(1)? != 0 {
+                    bump_sp = false;
+                    self.sp = Program::address(self.param(2)?)?;
+                }
+            }
+            Opcode::JumpIfFalse => {
+                if self.param(1)? == 0 {
+                    bump_sp = false;
+                    self.sp = Program::address(self.param(2)?)?;
+                }
+            }
+            Opcode::LessThan => {
+                let pos = self.get_pos(3)?;
+                let result = if self.param(1)? < self.param(2)? { 1 } else { 0 };
+                self.write(pos, result);
+            }
+            Opcode::Equals => {
+                let pos = self.get_pos(3)?;
+                let result = if self.param(1)? == self.param(2)? { 1 } else { 0 };
+                self.write(pos, result);
+            }
+            Opcode::RelativeBaseOffset => self.offset_relative_base(self.param(1)?)?,
+            Opcode::Halt => {
+                self.log_event(Event::Halt);
+                self.state = State::Halted;
+            }
+        }
+        Ok(Some(bump_sp))
+    }
+
+    fn offset_relative_base(&mut self, offset: Number) -> Result<(), RunError> {
+        let old = self.relative_base;
+        self.relative_base = old.checked_add(offset).ok_or(RunError::AddressOverflow)?;
+        self.counters.relative_base_changes += 1;
+        if let Some(hook) = self.relative_base_hook.as_mut() {
+            hook(old, self.relative_base);
+        }
+        Ok(())
+    }
+
+    fn execute_custom(&mut self, opcode: Number, width: usize, handler: &mut OpcodeHandler) {
+        if self.pc_history_capacity > 0 {
+            self.record_pc();
+        }
+        let addr = self.sp;
+        self.log_event(Event::Exec { addr, opcode });
+        handler(self);
+        self.counters.steps += 1;
+        if self.sp == addr {
+            self.sp += width;
+        }
+    }
+
+    /// Adds an opcode of your own, which runs `handler` whenever the program
+    /// executes it. The handler gets the program, with the stack pointer still
+    /// on the instruction.
+    ///
+    /// `width` is the number of memory cells the instruction takes up,
+    /// including the opcode itself. After the handler returns, the stack
+    /// pointer is moved past the instruction by that many cells, unless the
+    /// handler moved it somewhere else, the way jumps do. The parameter modes
+    /// in the instruction are not interpreted, and a trace function set with
+    /// `set_trace` is not called for these instructions. Registering the same
+    /// opcode again replaces its handler.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `code` is one of the standard opcodes, or isn't between 0
+    /// and 99, since only the last two digits of an instruction are its
+    /// opcode.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![50, 50, 104, 1, 99]);
+    /// // Opcode 50 adds a 0 to the input queue, and takes up a single cell.
+    /// program.register_opcode(50, 1, |p| p.push_input(0));
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.pending_input(), &[0, 0]);
+    /// assert_eq!(program.get_output(), Some(1));
+    /// ```
+    pub fn register_opcode<F: FnMut(&mut Program) + Send + 'static>(&mut self, code: Number, width: usize, handler: F) {
+        if !(0..100).contains(&code) || Opcode::decode(code).is_some() {
+            panic!("Can't register opcode {}", code);
+        }
+        self.custom_opcodes.insert(code, (width, Box::new(handler)));
+    }
+
+    /// Returns the state the program is in.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, State};
+    ///
+    /// let mut program = Program::new(vec![3, 0, 99]);
+    /// assert_eq!(program.state(), State::Running);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.state(), State::WaitingForInput);
+    /// ```
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns where the program is in its execution, as the stack pointer and
+    /// the relative base. Together with `set_cursor`, this makes it possible
+    /// to go back and run part of a program again, without saving the rest of
+    /// its state.
+    ///
+    /// # Example
+    /// ```
+    /// // Sets the relative base to 5, outputs the value at address 5, then
+    /// // waits for input.
+    /// let mut program = intcode::Program::new(vec![109, 5, 204, 0, 3, 7, 99]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.cursor(), (4, 5));
+    ///
+    /// program.set_cursor(2, 5);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.pending_output(), &[7, 7]);
+    /// ```
+    pub fn cursor(&self) -> (usize, Number) {
+        (self.sp, self.relative_base)
+    }
+
+    /// Moves the program to another point in its execution, by setting the
+    /// stack pointer and the relative base. Memory and the queues are left
+    /// alone.
+    ///
+    /// A program that was waiting for input is Running again afterwards, since
+    /// it is no longer at the instruction that was waiting, but a halted
+    /// program stays halted.
+    pub fn set_cursor(&mut self, sp: usize, relative_base: Number) {
+        self.sp = sp;
+        self.relative_base = relative_base;
+        self.paused_at = None;
+        if let State::WaitingForInput = self.state {
+            self.state = State::Running;
+        }
+    }
+
+    /// Returns `true` if and only if the program is in the "halted" state. This
+    /// can only happen if the appropriate opcode has been executed, or if the
+    /// program ran out of input under `InputPolicy::Halt` or after
+    /// `close_input`.
+    pub fn halted(&self) -> bool {
+        match self.state {
+            State::Running => false,
+            State::Halted => true,
+            State::WaitingForInput => false,
+        }
+    }
+
+    /// Returns `true` if the program is idle: it is waiting for input, or the
+    /// last input it read was the value given by `InputPolicy::Value` because
+    /// its queue was empty, and it hasn't produced any output since the last
+    /// time this method was called.
+    ///
+    /// Each call remembers how many outputs the program has produced, and the
+    /// next call compares against that, so the first call only returns `true`
+    /// if the program has never produced output at all. `reset`, `soft_reset`
+    /// and `clear_output` start the count over.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{InputPolicy, Program};
+    ///
+    /// // Outputs 7 once, then keeps reading input.
+    /// let mut program = Program::new(vec![104, 7, 3, 100, 1105, 1, 2]);
+    /// program.set_input_policy(InputPolicy::Value(-1));
+    /// program.set_pause_on_output(true);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert!(!program.is_idle());
+    ///
+    /// program.step_verbose();
+    /// assert!(program.is_idle());
+    /// ```
+    pub fn is_idle(&self) -> bool {
+        let idle = (self.state == State::WaitingForInput || self.read_sentinel)
+            && self.output.len() == self.idle_mark.get();
+        self.idle_mark.set(self.output.len());
+        idle
+    }
+
+    /// Returns `true` if and only if the program is in the "halted" state, or
+    /// is waiting for input.
+    pub fn halted_or_blocked(&self) -> bool {
+        match self.state {
+            State::Running => false,
+            State::Halted => true,
+            State::WaitingForInput => true,
+        }
+    }
+
+    /// Starts running the program until it can't run any further.
+    ///
+    /// This will go through the instructions of the program until it halts, or
+    /// encounters an "input" opcode but has no input. If the latter happens,
+    /// then you can call this method again after supplying input to make the
+    /// program resume execution. With `set_pause_on_output` turned on, it also
+    /// returns after every output.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `try_run`, with the stack pointer left on
+    /// the instruction that caused it. Use `run_or_panic` to panic instead.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{Program, RunError};
+    ///
+    /// let mut program = Program::new(vec![3, 5, 4, 5, 99, 0]);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// assert_eq!(program.get_output(), None);
+    /// assert!(!program.halted());
+    ///
+    /// program.push_input(123);
+    /// program.run_till_halted_or_blocked().unwrap();
+    ///
+    /// assert_eq!(program.get_output(), Some(123));
+    /// assert!(program.halted());
+    ///
+    /// let mut program = Program::new(vec![104, 1, 42, 99]);
+    /// assert_eq!(
+    ///     program.run_till_halted_or_blocked(),
+    ///     Err(RunError::UnknownOpcode { sp: 2, opcode: 42 }),
+    /// );
+    /// ```
+    pub fn run_till_halted_or_blocked(&mut self) -> Result<(), RunError> {
+        if !self.pause_on_output {
+            return self.try_run();
+        }
+        while !self.halted_or_blocked() {
+            let outputs = self.counters.opcodes[Opcode::Output as usize];
+            self.try_execute_instruction()?;
+            if self.counters.opcodes[Opcode::Output as usize] != outputs {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the program like `run_till_halted_or_blocked`, but panics with a
+    /// description of the problem instead of returning an error. If a
+    /// function was given to `set_error_handler`, it is called instead, and
+    /// the program halts.
+    ///
+    /// # Panics
+    ///
+    /// Panics in any of the cases where `try_run` would return an error,
+    /// unless an error handler is set.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 7, 99]);
+    /// program.run_or_panic();
+    /// assert_eq!(program.get_output(), Some(7));
+    /// ```
+    pub fn run_or_panic(&mut self) {
+        if let Err(e) = self.run_till_halted_or_blocked() {
+            self.handle_error(e);
+        }
+    }
+
+    /// Runs the program until it halts or needs input, whether or not
+    /// `set_pause_on_output` is turned on.
+    fn run_without_pausing(&mut self) {
+        while !self.halted_or_blocked() {
+            self.execute_instruction();
+        }
+    }
+
+    /// Runs the program until it halts or needs input, like
+    /// `run_till_halted_or_blocked`, and returns its memory and outputs along
+    /// with how the run went, for puzzles that need more than one of them.
+    ///
+    /// The outputs returned are all the ones that weren't consumed yet,
+    /// including any from before this call, and they are consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![1101, 2, 3, 0, 4, 0, 99]);
+    /// let summary = program.run_result();
+    /// assert_eq!(summary.memory, vec![5, 2, 3, 0, 4, 0, 99]);
+    /// assert_eq!(summary.outputs, vec![5]);
+    /// assert!(summary.halted);
+    /// assert_eq!(summary.steps, 3);
+    /// ```
+    pub fn run_result(&mut self) -> RunSummary {
+        let steps = self.counters.steps;
+        self.run_without_pausing();
+        let outputs = self.pending_output().to_vec();
+        self.output_pos = self.output.len();
+        RunSummary {
+            memory: self.effective_memory(),
+            outputs,
+            halted: self.halted(),
+            steps: (self.counters.steps - steps) as usize,
+        }
+    }
+
+    /// Runs the program until it halts or needs input, like
+    /// `run_till_halted_or_blocked`, and returns how many outputs it produced
+    /// while doing so.
+    ///
+    /// Outputs are counted whether or not they are queued, so this is also
+    /// useful together with `on_output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// // Outputs its input, and the input plus one.
+    /// let mut program = intcode::Program::new(vec![3, 100, 4, 100, 101, 1, 100, 100, 4, 100, 1105, 1, 0]);
+    /// assert_eq!(program.run(), 0);
+    /// program.push_input(5);
+    /// assert_eq!(program.run(), 2);
+    /// assert_eq!(program.get_output(), Some(5));
+    /// assert_eq!(program.get_output(), Some(6));
+    /// ```
+    pub fn run(&mut self) -> usize {
+        let before = self.counters.opcodes[Opcode::Output as usize];
+        self.run_without_pausing();
+        (self.counters.opcodes[Opcode::Output as usize] - before) as usize
+    }
+
+    /// Runs the program until it can't run any further, like
+    /// `run_till_halted_or_blocked`, but without returning after every output
+    /// when `set_pause_on_output` is turned on.
+    ///
+    /// When an error is returned, the stack pointer still points at the
+    /// instruction that caused it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RunError::UnknownOpcode`, `RunError::UnknownParameterMode` or
+    /// `RunError::WriteToImmediate` if an instruction can't be executed as it
+    /// is. Returns `RunError::InputExhausted` if the program needs input that
+    /// isn't there while the input policy is `InputPolicy::Error`, and
+    /// `RunError::UninitializedRead` if it reads memory that was never written
+    /// while strict reads are turned on. Returns `RunError::NegativeAddress`
+    /// or `RunError::AddressOverflow` if it uses an address that can't exist,
+    /// and `RunError::UnexpectedOutput` if it outputs something other than
+    /// what was given to `set_expected_output`. Returns
+    /// `RunError::InputFailed` or `RunError::OutputFailed` if reading from the
+    /// reader given to `set_input_reader` or writing to the writer given to
+    /// `set_output_writer` fails, and `RunError::ArithmeticOverflow` if the
+    /// result of an addition or multiplication doesn't fit in a `Number`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{InputPolicy, Program, RunError};
+    ///
+    /// let mut program = Program::new(vec![3, 5, 4, 5, 99, 0]);
+    /// program.set_input_policy(InputPolicy::Error);
+    /// assert_eq!(program.try_run(), Err(RunError::InputExhausted));
+    ///
+    /// program.push_input(3);
+    /// assert_eq!(program.try_run(), Ok(()));
+    /// assert_eq!(program.get_output(), Some(3));
+    /// ```
+    pub fn try_run(&mut self) -> Result<(), RunError> {
+        while !self.halted_or_blocked() {
+            self.try_execute_instruction()?;
+        }
+        Ok(())
+    }
+
+    /// Runs the program like `try_run`, but faster, by doing less work for
+    /// each instruction. This is meant for long-running programs that don't
+    /// need to wait for input along the way.
+    ///
+    /// Each instruction is decoded only once, and the program's read and write
+    /// statistics, including `distinct_writes`, are not kept up to date. The
+    /// rest of `profile_report` is. Input is handled as usual, so the program
+    /// still stops when it needs input that isn't there, or follows the input
+    /// policy. If a trace function is set, the PC history or the event log is
+    /// enabled, strict reads are turned on, outputs are being checked with
+    /// `set_expected_output` or opcodes were added with `register_opcode`,
+    /// this simply calls `try_run`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `try_run`.
+    ///
+    /// # Example
+    /// ```
+    /// // Counts address 10 down to 0, then outputs it.
+    /// let mut program = intcode::Program::new(vec![1001, 10, -1, 10, 1005, 10, 0, 4, 10, 99, 1000]);
+    /// program.run_fast().unwrap();
+    /// assert!(program.halted());
+    /// assert_eq!(program.get_output(), Some(0));
+    /// ```
+    pub fn run_fast(&mut self) -> Result<(), RunError> {
+        if self.trace.is_some()
+            || self.pc_history_capacity > 0
+            || self.event_log.is_some()
+            || self.strict_reads
+            || self.expected_output.is_some()
+            || !self.custom_opcodes.is_empty()
+        {
+            return self.try_run();
+        }
+        if self.halted_or_blocked() {
+            return Ok(());
+        }
+
+        loop {
+            let raw = self.get_mem(self.sp);
+            // Check every mode digit, not just the ones this opcode uses, so
+            // that bad instructions fail the same way they do in `try_run`.
+            if let Some(problem) = self.instruction_problem(raw) {
+                return Err(problem);
+            }
+            let Some(opcode) = Opcode::decode(raw % 100) else {
+                return Err(RunError::UnknownOpcode { sp: self.sp, opcode: raw % 100 });
+            };
+            let mut next = self.sp + opcode.width();
+            match opcode {
+                Opcode::Add | Opcode::Multiply | Opcode::LessThan | Opcode::Equals => {
+                    let a = self.fast_param(raw, 1)?;
+                    let b = self.fast_param(raw, 2)?;
+                    let val = match opcode {
+                        Opcode::Add => a.checked_add(b),
+                        Opcode::Multiply => a.checked_mul(b),
+                        Opcode::LessThan => Some((a < b) as Number),
+                        _ => Some((a == b) as Number),
+                    };
+                    let val = val.ok_or(RunError::ArithmeticOverflow { sp: self.sp })?;
+                    self.set_mem(self.fast_pos(raw, 3)?, val);
+                }
+                Opcode::Input => {
+                    if self.input_pos == self.input.len() {
+                        // Leave the input policy to the general code.
+                        self.try_execute_instruction()?;
+                        if self.halted_or_blocked() {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                    let pos = self.fast_pos(raw, 1)?;
+                    let input = self.get_input();
+                    self.read_sentinel = false;
+                    self.set_mem(pos, input);
+                }
+                Opcode::Output => self.push_output(self.fast_param(raw, 1)?)?,
+                Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+                    if (self.fast_param(raw, 1)? != 0) == (opcode == Opcode::JumpIfTrue) {
+                        next = Program::address(self.fast_param(raw, 2)?)?;
+                    }
+                }
+                Opcode::RelativeBaseOffset => self.offset_relative_base(self.fast_param(raw, 1)?)?,
+                Opcode::Halt => next = self.sp,
+            }
+            self.counters.steps += 1;
+            self.counters.opcodes[opcode as usize] += 1;
+            self.sp = next;
+            if opcode == Opcode::Halt {
+                self.state = State::Halted;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the value of a parameter for `run_fast`, given the instruction
+    /// it belongs to.
+    fn fast_param(&self, instruction: Number, param: usize) -> Result<Number, RunError> {
+        let value = self.get_mem(self.sp + param);
+        Ok(match self.fast_mode(instruction, param)? {
+            0 => self.get_mem(Program::address(value)?),
+            1 => value,
+            _ => self.get_mem(self.relative_address(value)?),
+        })
+    }
+
+    /// Returns the position a parameter refers to for `run_fast`, given the
+    /// instruction it belongs to.
+    fn fast_pos(&self, instruction: Number, param: usize) -> Result<usize, RunError> {
         let pos = self.get_mem(self.sp + param);
+        match self.fast_mode(instruction, param)? {
+            0 => Program::address(pos),
+            1 => Err(RunError::WriteToImmediate { sp: self.sp }),
+            _ => self.relative_address(pos),
+        }
+    }
 
-        let mode = match param {
-            1 => instruction.param1,
-            2 => instruction.param2,
-            3 => instruction.param3,
-            _ => unreachable!()
-        };
+    fn fast_mode(&self, instruction: Number, param: usize) -> Result<Number, RunError> {
+        const DIVISORS: [Number; 3] = [100, 1000, 10000];
+        match instruction / DIVISORS[param - 1] % 10 {
+            mode @ 0..=2 => Ok(mode),
+            mode => Err(RunError::UnknownParameterMode { sp: self.sp, mode }),
+        }
+    }
+
+    /// Runs the program until it needs input that isn't there yet, or until it
+    /// halts. Returns `true` in the first case and `false` in the second.
+    ///
+    /// When this returns `true`, the stack pointer still points at the "input"
+    /// instruction, which has not been executed. It will be executed from the
+    /// start, reading the next input, once the program is run again after
+    /// input has been pushed. Input that is already queued is read without
+    /// stopping.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![3, 9, 4, 9, 1105, 1, 0]);
+    /// assert!(program.run_to_input());
+    /// program.push_input(5);
+    /// assert!(program.run_to_input());
+    /// assert_eq!(program.get_output(), Some(5));
+    ///
+    /// let mut program = intcode::Program::new(vec![104, 1, 99]);
+    /// assert!(!program.run_to_input());
+    /// ```
+    pub fn run_to_input(&mut self) -> bool {
+        self.run_without_pausing();
+        self.state == State::WaitingForInput
+    }
+
+    /// Runs the program until it outputs something, needs input, or halts,
+    /// whichever comes first, and says which it was. This is for drivers that
+    /// handle both input and output as they happen, such as an event loop
+    /// that runs several programs.
+    ///
+    /// An output that is already waiting in the queue is returned straight
+    /// away, before anything is executed. Outputs are consumed as they are
+    /// returned, and must be queued for this to work; see `set_queue_output`.
+    /// Every call either returns right away or executes at least one
+    /// instruction, so calling this in a loop always makes progress, as long
+    /// as input is pushed when it is needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::IoEvent;
+    ///
+    /// // Outputs 1, then outputs its input, then halts.
+    /// let mut program = intcode::Program::new(vec![104, 1, 3, 100, 4, 100, 99]);
+    /// assert_eq!(program.run_to_io(), IoEvent::Output(1));
+    /// assert_eq!(program.run_to_io(), IoEvent::NeedInput);
+    /// program.push_input(5);
+    /// assert_eq!(program.run_to_io(), IoEvent::Output(5));
+    /// assert_eq!(program.run_to_io(), IoEvent::Halted);
+    /// ```
+    pub fn run_to_io(&mut self) -> IoEvent {
+        loop {
+            if let Some(value) = self.get_output() {
+                return IoEvent::Output(value);
+            }
+            match self.state {
+                State::Running => self.execute_instruction(),
+                State::WaitingForInput => return IoEvent::NeedInput,
+                State::Halted => return IoEvent::Halted,
+            }
+        }
+    }
+
+    /// Returns an iterator over the program's outputs, which runs the program
+    /// only as far as it needs to for each one, using `run_to_io`. The
+    /// iterator ends when the program halts or needs input, and outputs are
+    /// consumed as they are returned.
+    ///
+    /// Since the program isn't run any further than needed, something like
+    /// `take(2)` leaves the rest of the program to run later. Once the
+    /// iterator has ended, pushing input and calling this again picks up where
+    /// the program left off.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// let first: Vec<_> = program.outputs().take(2).collect();
+    /// assert_eq!(first, vec![1, 2]);
+    /// assert!(!program.halted());
+    /// assert_eq!(program.outputs().collect::<Vec<_>>(), vec![3]);
+    /// ```
+    pub fn outputs(&mut self) -> impl Iterator<Item = Number> + '_ {
+        std::iter::from_fn(move || match self.run_to_io() {
+            IoEvent::Output(value) => Some(value),
+            IoEvent::NeedInput | IoEvent::Halted => None,
+        })
+    }
+
+    /// Runs the program until it next needs input, using `run_to_input`, and
+    /// consumes and returns all of its outputs. This is one frame of a game
+    /// that draws the screen and then reads the player's move.
+    ///
+    /// If the program halts during the frame instead, the outputs up to that
+    /// point are returned all the same. Use `halted` to tell the two apart.
+    /// Outputs that were produced before this call and not consumed yet come
+    /// first.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// // Outputs 1 and 2, reads input, and outputs 3 before halting.
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 3, 100, 104, 3, 99]);
+    /// assert_eq!(program.run_frame(), vec![1, 2]);
+    /// assert!(!program.halted());
+    ///
+    /// program.push_input(0);
+    /// assert_eq!(program.run_frame(), vec![3]);
+    /// assert!(program.halted());
+    /// ```
+    pub fn run_frame(&mut self) -> Vec<Number> {
+        self.run_to_input();
+        let frame = self.pending_output().to_vec();
+        self.output_pos = self.output.len();
+        frame
+    }
+
+    /// Runs the program until `pred` returns `true`, or until it can't run any
+    /// further. The predicate is checked after every instruction.
+    ///
+    /// Returns `true` if the predicate was satisfied, or `false` if the program
+    /// halted or blocked first. If neither ever happens, for example because
+    /// the program loops forever and the predicate can never become `true`,
+    /// then this method never returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// assert!(program.run_until(|p| p.last_output() == Some(2)));
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.get_output(), Some(2));
+    /// assert_eq!(program.get_output(), None);
+    /// ```
+    pub fn run_until<F: FnMut(&Program) -> bool>(&mut self, mut pred: F) -> bool {
+        self.run_with(|p| if pred(p) { ControlFlow::Break(()) } else { ControlFlow::Continue(()) })
+            .is_break()
+    }
+
+    /// Runs the program for at most `budget` instructions, stopping early if
+    /// it halts or needs input, and returns how much of the budget is left.
+    /// This is for sharing time fairly between many programs, where a
+    /// scheduler can carry over what one program didn't use.
+    ///
+    /// An "input" instruction that finds no input doesn't count, since it
+    /// isn't executed until input arrives.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// assert_eq!(program.run_budget(2), 0);
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// assert_eq!(program.run_budget(5), 3);
+    /// assert!(program.halted());
+    /// ```
+    pub fn run_budget(&mut self, budget: usize) -> usize {
+        let mut remaining = budget;
+        while remaining > 0 && !self.halted_or_blocked() {
+            let steps = self.counters.steps;
+            self.execute_instruction();
+            remaining -= (self.counters.steps - steps) as usize;
+        }
+        remaining
+    }
 
-        match mode {
-            ParameterMode::Position => { pos as usize },
-            ParameterMode::Immediate => { panic!("Can't get an immediate position!") },
-            ParameterMode::Relative => { (self.relative_base + pos) as usize },
+    /// Runs the program for at most `max_steps` instructions, and returns
+    /// whether it halted, needs input, or used up all of its steps. Nothing
+    /// else is executed after the last step, so calling this again picks up
+    /// where the program left off. This guards against programs that would
+    /// otherwise loop forever.
+    ///
+    /// Steps are counted the same way as for `run_budget`. A program that
+    /// halts or needs input on its very last step reports that rather than
+    /// `RunOutcome::StepLimitReached`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::RunOutcome;
+    ///
+    /// // Outputs 1, 2, 3, ... forever.
+    /// let mut program = intcode::Program::new(vec![1001, 9, 1, 9, 4, 9, 1105, 1, 0, 0]);
+    /// assert_eq!(program.run_with_step_limit(6), RunOutcome::StepLimitReached);
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    ///
+    /// let mut program = intcode::Program::new(vec![3, 0, 99]);
+    /// assert_eq!(program.run_with_step_limit(10), RunOutcome::WaitingForInput);
+    /// program.push_input(5);
+    /// assert_eq!(program.run_with_step_limit(10), RunOutcome::Halted);
+    /// ```
+    pub fn run_with_step_limit(&mut self, max_steps: usize) -> RunOutcome {
+        self.run_budget(max_steps);
+        match self.state {
+            State::Running => RunOutcome::StepLimitReached,
+            State::WaitingForInput => RunOutcome::WaitingForInput,
+            State::Halted => RunOutcome::Halted,
         }
     }
 
-    fn get_mem(&self, pos: usize) -> Number {
-        if pos < self.program.len() {
-            self.program[pos]
-        } else if self.extra_memory.contains_key(&pos) {
-            *self.extra_memory.get(&pos).unwrap()
-        } else {
-            0
+    /// Runs the program until it outputs `sentinel`, and returns the outputs
+    /// up to and including the sentinel. Outputs that were already waiting in
+    /// the output queue come first, and the ones returned are consumed.
+    ///
+    /// If the program halts or needs input before outputting the sentinel,
+    /// this returns all outputs up to that point, so the sentinel is the last
+    /// value returned if and only if it was found. Outputs must be queued for
+    /// this to work; see `set_queue_output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, -1, 104, 2, 99]);
+    /// assert_eq!(program.run_until_output_value(-1), vec![1, -1]);
+    /// assert_eq!(program.run_until_output_value(-1), vec![2]);
+    /// assert!(program.halted());
+    /// ```
+    pub fn run_until_output_value(&mut self, sentinel: Number) -> Vec<Number> {
+        let mut outputs = Vec::new();
+        loop {
+            if let Some(val) = self.get_output() {
+                outputs.push(val);
+                if val == sentinel {
+                    return outputs;
+                }
+            } else if self.halted_or_blocked() {
+                return outputs;
+            } else {
+                self.execute_instruction();
+            }
         }
     }
 
-    fn set_mem(&mut self, pos: usize, val: Number) {
-        if pos < self.program.len() {
-            self.program[pos] = val;
-        } else {
-            self.extra_memory.insert(pos, val);
+    /// Runs the program until it can't run any further, calling `f` after
+    /// every instruction. If `f` returns `ControlFlow::Break`, the program
+    /// stops right there, and can be resumed later by calling any of the run
+    /// methods again.
+    ///
+    /// Returns `ControlFlow::Break` if `f` stopped the program, and
+    /// `ControlFlow::Continue` if the program halted or blocked.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::ops::ControlFlow;
+    ///
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 104, 3, 99]);
+    /// let mut steps = 0;
+    /// let result = program.run_with(|_| {
+    ///     steps += 1;
+    ///     if steps == 2 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    /// });
+    /// assert!(result.is_break());
+    /// assert_eq!(program.last_output(), Some(2));
+    ///
+    /// assert!(program.run_with(|_| ControlFlow::Continue(())).is_continue());
+    /// assert!(program.halted());
+    /// ```
+    pub fn run_with<F: FnMut(&mut Program) -> ControlFlow<()>>(&mut self, mut f: F) -> ControlFlow<()> {
+        while !self.halted_or_blocked() {
+            self.execute_instruction();
+            f(self)?;
         }
+        ControlFlow::Continue(())
     }
 
-    fn execute_instruction(&mut self) {
-        let instruction = Instruction::from(self.get_mem(self.sp));
-        let mut bump_sp = true;
-
-        if let ProgramState::Halted = self.state {
-            panic!("Attempted to run a halted program.");
-        }
-
-        match instruction.opcode {
-            Opcode::Add => {
-                let pos = self.get_pos(3);
-                self.set_mem(pos as usize, self.param(1) + self.param(2));
-            }
-            Opcode::Multiply => {
-                let pos = self.get_pos(3);
-                self.set_mem(pos as usize, self.param(1) * self.param(2));
-            }
-            Opcode::Input => {
-                if self.input.len() > self.input_pos {
-                    let input = self.get_input();
-                    self.set_mem(self.get_pos(1) as usize, input);
-                } else {
-                    bump_sp = false;
-                    self.state = ProgramState::WaitingForInput;
-                }
-            }
-            Opcode::Output => {
-                self.push_output(self.param(1));
-            }
-            Opcode::JumpIfTrue => {
-                if self.param(1) != 0 {
-                    bump_sp = false;
-                    self.sp = self.param(2) as usize;
-                }
-            }
-            Opcode::JumpIfFalse => {
-                if self.param(1) == 0 {
-                    bump_sp = false;
-                    self.sp = self.param(2) as usize;
-                }
-            }
-            Opcode::LessThan => {
-                let pos = self.get_pos(3);
-                let result = if self.param(1) < self.param(2) { 1 } else { 0 };
-                self.set_mem(pos as usize, result);
-            }
-            Opcode::Equals => {
-                let pos = self.get_pos(3);
-                let result = if self.param(1) == self.param(2) { 1 } else { 0 };
-                self.set_mem(pos as usize, result);
-            }
-            Opcode::RelativeBaseOffset => {
-                self.relative_base += self.param(1);
+    /// Runs the program until the stack pointer reaches `addr`, or until it
+    /// can't run any further. The stack pointer is checked before every
+    /// instruction, so the instruction at `addr` will not have been executed
+    /// yet when this method returns.
+    ///
+    /// Returns `true` if the address was reached, and `false` if the program
+    /// halted or blocked first. If the stack pointer is already at `addr`, this
+    /// returns `true` straight away without executing anything.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `run_or_panic`.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::new(vec![104, 1, 104, 2, 99]);
+    /// assert!(program.run_to_address(2));
+    /// assert_eq!(program.get_output(), Some(1));
+    /// assert_eq!(program.get_output(), None);
+    ///
+    /// assert!(!program.run_to_address(0));
+    /// assert!(program.halted());
+    /// ```
+    pub fn run_to_address(&mut self, addr: usize) -> bool {
+        loop {
+            if self.sp == addr {
+                return true;
             }
-            Opcode::Halt => {
-                self.state = ProgramState::Halted;
+            if self.halted_or_blocked() {
+                return false;
             }
-        }
-        if bump_sp {
-            self.increase_sp();
+            self.execute_instruction();
         }
     }
 
-    /// Returns `true` if and only if the program is in the "halted" state. This
-    /// can only happen if the appropriate opcode has been executed.
-    pub fn halted(&mut self) -> bool {
-        match self.state {
-            ProgramState::Running => false,
-            ProgramState::Halted => true,
-            ProgramState::WaitingForInput => false,
-        }
+    /// Adds a breakpoint at `addr`, which makes `run_to_breakpoint` stop every
+    /// time the program is about to execute the instruction there.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{BreakReason, Program};
+    ///
+    /// let mut program = Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.add_breakpoint(2);
+    ///
+    /// assert_eq!(program.run_to_breakpoint(), BreakReason::Breakpoint(2));
+    /// assert_eq!(program.pending_output(), &[1]);
+    /// assert_eq!(program.run_to_breakpoint(), BreakReason::Halted);
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// ```
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.add_conditional_breakpoint(addr, |_| true);
     }
 
-    /// Returns `true` if and only if the program is in the "halted" state, or
-    /// is waiting for input.
-    pub fn halted_or_blocked(&mut self) -> bool {
-        match self.state {
-            ProgramState::Running => false,
-            ProgramState::Halted => true,
-            ProgramState::WaitingForInput => true,
-        }
+    /// Adds a breakpoint at `addr` that only makes `run_to_breakpoint` stop if
+    /// `cond` returns `true`. The condition is checked every time the program
+    /// is about to execute the instruction at `addr`, before executing it.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::{BreakReason, Program};
+    ///
+    /// // Counts up from 1 at address 100, outputting every number.
+    /// let mut program = Program::new(vec![101, 1, 100, 100, 4, 100, 1105, 1, 0]);
+    /// program.add_conditional_breakpoint(4, |p| p.pending_output().len() == 2);
+    ///
+    /// assert_eq!(program.run_to_breakpoint(), BreakReason::Breakpoint(4));
+    /// assert_eq!(program.pending_output(), &[1, 2]);
+    /// ```
+    pub fn add_conditional_breakpoint<F: FnMut(&Program) -> bool + Send + 'static>(&mut self, addr: usize, cond: F) {
+        self.breakpoints.push((addr, Box::new(cond)));
     }
 
-    /// Starts running the program until it can't run any further.
+    /// Adds a watchpoint at `addr`, which makes `run_to_breakpoint` stop right
+    /// after the program writes to that address, even if the value written is
+    /// the one that was already there.
     ///
-    /// This will go through the instructions of the program until it halts, or
-    /// encounters an "input" opcode but has no input. If the latter happens,
-    /// then you can call this method again after supplying input to make the
-    /// program resume execution.
+    /// # Example
+    /// ```
+    /// use intcode::{BreakReason, Program};
     ///
-    /// # Panics
+    /// let mut program = Program::new(vec![1101, 2, 3, 100, 104, 0, 1102, 2, 3, 100, 99]);
+    /// program.add_watchpoint(100);
     ///
-    /// Panics if the program is in "halted" state when the method is called, or
-    /// if an unknown opcode is encountered.
+    /// let reason = program.run_to_breakpoint();
+    /// assert_eq!(reason, BreakReason::Watchpoint { addr: 100, old: 0, new: 5 });
+    /// let reason = program.run_to_breakpoint();
+    /// assert_eq!(reason, BreakReason::Watchpoint { addr: 100, old: 5, new: 6 });
+    /// assert_eq!(program.get_output(), Some(0));
+    /// assert_eq!(program.run_to_breakpoint(), BreakReason::Halted);
+    /// ```
+    pub fn add_watchpoint(&mut self, addr: usize) {
+        self.watchpoints.insert(addr);
+    }
+
+    /// Removes all breakpoints, including conditional ones. Watchpoints are
+    /// kept.
     ///
     /// # Example
     /// ```
-    /// let mut program = intcode::Program::new(&vec![3, 5, 4, 5, 99, 0]);
-    /// program.run_till_halted_or_blocked();
+    /// use intcode::{BreakReason, Program};
     ///
-    /// assert_eq!(program.get_output(), None);
-    /// assert!(!program.halted());
+    /// let mut program = Program::new(vec![104, 1, 104, 2, 99]);
+    /// program.add_breakpoint(2);
+    /// program.clear_breakpoints();
+    /// assert_eq!(program.run_to_breakpoint(), BreakReason::Halted);
+    /// ```
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Runs the program until it reaches a breakpoint, writes to a watched
+    /// address, halts, or needs input. When it stops at a breakpoint, the
+    /// instruction there has not been executed yet. Running again from there
+    /// executes it without stopping at the same breakpoint first, also when
+    /// that instruction is an "input" that had to wait for input in between.
     ///
-    /// program.push_input(123);
-    /// program.run_till_halted_or_blocked();
+    /// # Panics
     ///
-    /// assert_eq!(program.get_output(), Some(123));
-    /// assert!(program.halted());
-    /// ```
-    pub fn run_till_halted_or_blocked(&mut self) {
-        while !self.halted_or_blocked() {
+    /// Panics under the same conditions as `run_or_panic`.
+    pub fn run_to_breakpoint(&mut self) -> BreakReason {
+        let mut resuming = self.paused_at.take() == Some(self.sp);
+        loop {
+            match self.state {
+                State::Halted => return BreakReason::Halted,
+                State::WaitingForInput => {
+                    if resuming {
+                        // The instruction at the breakpoint is an "input" that
+                        // is still waiting, so it hasn't been executed yet.
+                        self.paused_at = Some(self.sp);
+                    }
+                    return BreakReason::WaitingForInput;
+                }
+                State::Running => {}
+            }
+            if !resuming && self.breakpoint_hit() {
+                self.paused_at = Some(self.sp);
+                return BreakReason::Breakpoint(self.sp);
+            }
+            let steps = self.counters.steps;
+            self.watch_hit = None;
             self.execute_instruction();
+            if self.counters.steps != steps {
+                resuming = false;
+            }
+            if let Some((addr, old, new)) = self.watch_hit.take() {
+                return BreakReason::Watchpoint { addr, old, new };
+            }
+        }
+    }
+
+    fn breakpoint_hit(&mut self) -> bool {
+        // The conditions need to see the program, so they can't be borrowed
+        // from it while they're being called.
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let hit = breakpoints
+            .iter_mut()
+            .any(|(addr, cond)| *addr == self.sp && cond(self));
+        self.breakpoints = breakpoints;
+        hit
+    }
+}
+
+/// Shows a one-line summary of the program's state, including the next
+/// instruction to be executed.
+///
+/// # Example
+/// ```
+/// let mut program = intcode::Program::new(vec![104, 1, 3, 100, 99]);
+/// program.run_till_halted_or_blocked().unwrap();
+/// assert_eq!(
+///     program.to_string(),
+///     "waiting for input, sp 2, relative base 0, 0 inputs and 1 outputs pending, next: IN -> @100",
+/// );
+/// ```
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Running => "running",
+            State::WaitingForInput => "waiting for input",
+            State::Halted => "halted",
+        };
+        write!(
+            f,
+            "{}, sp {}, relative base {}, {} inputs and {} outputs pending, next: ",
+            state,
+            self.sp,
+            self.relative_base,
+            self.input.len() - self.input_pos,
+            self.output.len() - self.output_pos,
+        )?;
+        match self.format_instruction(self.sp) {
+            Some(instruction) => write!(f, "{}", instruction),
+            None => write!(f, "invalid instruction {}", self.get_mem(self.sp)),
         }
     }
 }
 
 #[test]
 fn test_get_set_mem() {
-    let mut p = Program::new(&vec![1, 1, 1, 1]);
+    let mut p = Program::new(vec![1, 1, 1, 1]);
     assert_eq!(p.get_mem(0), 1);
     p.set_mem(0, 2);
     assert_eq!(p.get_mem(0), 2);
@@ -380,7 +3666,7 @@ fn test_get_set_mem() {
 
 #[test]
 fn test_get_set_extra_memory() {
-    let mut p = Program::new(&vec![1, 1, 1, 1]);
+    let mut p = Program::new(vec![1, 1, 1, 1]);
     p.set_mem(100, 2);
     assert_eq!(p.get_mem(100), 2);
 }
@@ -388,20 +3674,17 @@ fn test_get_set_extra_memory() {
 #[test]
 fn test_relative_mode() {
     let v = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
-    let mut p = Program::new(&v);
-    p.run_till_halted_or_blocked();
-    let mut v2 = Vec::new();
-    while let Some(i) = p.get_output() {
-        v2.push(i);
-    }
+    let mut p = Program::new(v.clone());
+    let v2: Vec<_> = p.outputs().collect();
     assert_eq!(v, v2);
+    assert!(p.halted());
 }
 
 #[test]
 fn test_large_numbers() {
     let v = vec![104,1125899906842624,99];
-    let mut p = Program::new(&v);
-    p.run_till_halted_or_blocked();
+    let mut p = Program::new(v);
+    p.run_till_halted_or_blocked().unwrap();
     assert_eq!(p.get_output().unwrap(), 1125899906842624);
 }
 
@@ -409,11 +3692,476 @@ fn test_large_numbers() {
 fn test_relative_mode2() {
     let v = vec![109,19,204,-34,99];
 
-    let mut p = Program::new(&v);
+    let mut p = Program::new(v);
     p.relative_base = 2000;
     p.set_mem(1985, 333333);
-    p.run_till_halted_or_blocked();
+    p.run_till_halted_or_blocked().unwrap();
 
     assert_eq!(p.get_output().unwrap(), 333333);
     assert_eq!(p.relative_base, 2019);
 }
+
+#[test]
+fn test_step_verbose_reports_halting_without_executing() {
+    let mut p = Program::new(vec![3, 0, 99]);
+    p.set_input_policy(InputPolicy::Halt);
+    assert_eq!(p.step_verbose(), (State::Halted, None));
+    assert_eq!(p.sp, 0);
+
+    let mut p = Program::new(vec![11101, 1, 1, 0, 99]);
+    p.set_error_handler(|_| {});
+    assert_eq!(p.step_verbose(), (State::Halted, None));
+
+    let mut p = Program::new(vec![99]);
+    let (state, instruction) = p.step_verbose();
+    assert_eq!(state, State::Halted);
+    assert_eq!(instruction.unwrap().mnemonic, "HALT");
+}
+
+#[test]
+fn test_reload_code_keeps_state() {
+    let mut p = Program::new(vec![109, 10, 1101, 1, 1, 0, 3, 0, 99]);
+    p.set_mem(100, 5);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.sp, 6);
+
+    // The new tape is longer, and the program writes past the end of the old
+    // one after it is loaded.
+    p.reload_code(vec![1, 1, 1, 1, 1, 1, 3, 0, 1101, 2, 3, 11, 99, 0]);
+    assert_eq!(p.sp, 6);
+    assert_eq!(p.relative_base, 10);
+    assert_eq!(p.get_mem(100), 5);
+    p.push_input(4);
+    p.run_till_halted_or_blocked().unwrap();
+    assert!(p.halted());
+    assert_eq!(p.get_mem(0), 4);
+    assert_eq!(p.get_mem(11), 5);
+    assert_eq!(p.distinct_writes(), 2);
+
+    p.reset();
+    assert_eq!(p.get_mem(0), 1);
+    assert_eq!(p.get_mem(100), 0);
+    assert_eq!(p.sp, 0);
+    assert_eq!(p.relative_base, 0);
+}
+
+#[test]
+fn test_disassembly_skips_data() {
+    let p = Program::new(vec![1101, 1, 2, 0, -5, 104, 7, 99]);
+    let addrs: Vec<_> = p.disassembly().map(|(addr, i)| (addr, i.is_some())).collect();
+    assert_eq!(addrs, vec![(0, true), (4, false), (5, true), (7, true)]);
+}
+
+#[test]
+fn test_fork_keeps_writes_in_an_overlay() {
+    let mut p = Program::new(vec![3, 5, 4, 5, 99, 0]);
+    p.write_mem(1000, 3);
+    p.run_till_halted_or_blocked().unwrap();
+    let mut fork = p.fork();
+    assert!(Arc::ptr_eq(&p.program, &fork.program));
+    assert!(Arc::ptr_eq(&p.extra, &fork.extra));
+
+    fork.push_input(8);
+    fork.run_till_halted_or_blocked().unwrap();
+    fork.write_mem(1000, 4);
+    assert!(Arc::ptr_eq(&p.program, &fork.program));
+    assert!(Arc::ptr_eq(&p.extra, &fork.extra));
+    assert_eq!((p.get_mem(5), p.get_mem(1000)), (0, 3));
+    assert_eq!((fork.get_mem(5), fork.get_mem(1000)), (8, 4));
+    assert_eq!(fork.modified_cells().collect::<Vec<_>>(), vec![(5, 8), (1000, 4)]);
+    assert_eq!(fork.effective_memory().len(), 1001);
+}
+
+#[test]
+fn test_overlay_is_folded_once_it_grows() {
+    let mut p = Program::new(vec![0; 10]);
+    p.write_mem(5000, 1);
+    let mut fork = p.fork();
+    for pos in 0..10 {
+        fork.write_mem(pos, 1);
+    }
+    assert!(Arc::ptr_eq(&p.program, &fork.program));
+    for pos in 0..4 * OVERLAY_LIMIT {
+        fork.write_mem(10 + pos, 1);
+    }
+    assert!(fork.overlay.len() <= OVERLAY_LIMIT);
+    assert!(!Arc::ptr_eq(&p.program, &fork.program));
+    assert!(!Arc::ptr_eq(&p.extra, &fork.extra));
+    assert_eq!(p.get_mem(5000), 1);
+    assert_eq!(p.extra_cells().count(), 1);
+    assert_eq!(fork.extra_cells().count(), 4 * OVERLAY_LIMIT + 1);
+    assert_eq!(p.effective_memory().iter().sum::<Number>(), 1);
+    assert_eq!(fork.effective_memory().iter().sum::<Number>(), 11 + 4 * OVERLAY_LIMIT as Number);
+}
+
+#[test]
+fn test_pc_history_wraps() {
+    let mut p = Program::new(vec![104, 1, 1105, 1, 0]);
+    p.enable_pc_history(3);
+    for _ in 0..10 {
+        p.execute_instruction();
+    }
+    assert_eq!(p.recent_pcs(), &[2, 0, 2]);
+    assert!(p.pc_history.len() <= 6);
+}
+
+#[test]
+fn test_trace_skips_blocked_input() {
+    use std::sync::{Arc, Mutex};
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&lines);
+    let mut p = Program::new(vec![3, 100, 99]);
+    p.set_trace(move |addr, instruction| log.lock().unwrap().push(format!("{} {}", addr, instruction)));
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.state(), State::WaitingForInput);
+    p.push_input(1);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(*lines.lock().unwrap(), vec!["0 IN -> @100", "2 HALT"]);
+}
+
+#[test]
+fn test_halted_program_returns_an_error() {
+    let mut p = Program::new(vec![99]);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.try_execute_instruction(), Err(RunError::Halted));
+}
+
+#[test]
+fn test_event_log_skips_failing_instructions() {
+    let mut p = Program::new(vec![104, 1, 4, -3, 99]);
+    p.enable_event_log();
+    assert_eq!(p.try_run(), Err(RunError::NegativeAddress(-3)));
+    assert_eq!(p.event_log(), &[Event::Exec { addr: 0, opcode: 4 }, Event::Output(1)]);
+
+    let mut p = Program::new(vec![104, 1, 4, 1, 99]);
+    p.enable_event_log();
+    p.set_expected_output(vec![1, 2]);
+    assert!(p.try_run().is_err());
+    assert_eq!(p.event_log(), &[Event::Exec { addr: 0, opcode: 4 }, Event::Output(1)]);
+}
+
+#[test]
+fn test_pc_history_skips_blocked_input() {
+    let mut p = Program::new(vec![3, 100, 99]);
+    p.enable_pc_history(5);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.recent_pcs(), &[] as &[usize]);
+    p.push_input(1);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.recent_pcs(), &[0, 2]);
+}
+
+#[test]
+fn test_pc_history_records_failing_instructions() {
+    let mut p = Program::new(vec![104, 1, 98, 99]);
+    p.enable_pc_history(5);
+    assert_eq!(p.try_run(), Err(RunError::UnknownOpcode { sp: 2, opcode: 98 }));
+    assert_eq!(p.recent_pcs(), &[0, 2]);
+
+    let mut p = Program::new(vec![104, 1, 4, -3, 99]);
+    p.enable_pc_history(5);
+    assert_eq!(p.try_run(), Err(RunError::NegativeAddress(-3)));
+    assert_eq!(p.recent_pcs(), &[0, 2]);
+}
+
+#[test]
+fn test_far_writes_stay_sparse() {
+    let mut p = Program::new(vec![1101, 1, 1, 1 << 40, 99]);
+    assert_eq!(p.try_run(), Ok(()));
+    assert_eq!(p.get_mem(1 << 40), 2);
+    assert!(p.extra.cells.is_empty());
+    assert_eq!(p.extra_cells().collect::<Vec<_>>(), vec![(1 << 40, 2)]);
+
+    let json = r#"{"sp":0,"relative_base":0,"state":"Running","memory":[99],"initial":[99],"extra_memory":[[100000000000000,1]],"input":[],"input_pos":0,"input_closed":false,"output":[],"output_pos":0}"#;
+    assert_eq!(Program::from_json(json).unwrap().get_mem(100_000_000_000_000), 1);
+}
+
+#[test]
+fn test_sparse_cells_move_into_the_block() {
+    // Writes 7 to address 2000, then 1 to every address from 30 up to 3000,
+    // through the relative base. The counter lives at 25 and 26.
+    let mut p = Program::new(vec![
+        1101, 0, 7, 2000, 109, 30, 21101, 0, 1, 0, 109, 1, 1001, 25, 1, 25, 1007, 25, 2970, 26, 1005, 26, 6, 99,
+    ]);
+    p.try_run().unwrap();
+    assert!(p.halted());
+    assert!(p.extra.sparse.is_empty());
+    assert_eq!(p.get_mem(2000), 1);
+    assert_eq!(p.get_mem(2999), 1);
+    assert_eq!(p.get_mem(3000), 0);
+    // Address 2000 is part of the sweep, so it only counts once.
+    assert_eq!(p.distinct_writes(), 2970 + 2);
+}
+
+#[test]
+fn test_compact_memory() {
+    let mut p = Program::new(vec![99]);
+    p.set_mem(10, 0);
+    p.set_mem(11, 4);
+    p.compact_memory();
+    assert_eq!(p.extra_cells().count(), 1);
+    assert_eq!(p.get_mem(10), 0);
+    assert_eq!(p.get_mem(11), 4);
+}
+
+#[test]
+fn test_instruction_overwriting_itself() {
+    // The add writes 104 over its own opcode. The stack pointer should still
+    // move past the four cells of the add, not the two of an output.
+    let v = vec![1101, 100, 4, 0, 104, 7, 99];
+    let mut p = Program::new(v);
+    p.run_till_halted_or_blocked().unwrap();
+    assert_eq!(p.get_output(), Some(7));
+    assert_eq!(p.get_mem(0), 104);
+}
+
+#[test]
+fn test_run_to_input_resumes_at_input() {
+    let mut p = Program::new(vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99]);
+    assert!(p.run_to_input());
+    assert_eq!(p.sp, 0);
+
+    p.push_input(2);
+    assert!(p.run_to_input());
+    assert_eq!(p.sp, 2);
+    assert_eq!(p.get_mem(11), 2);
+
+    p.push_input(3);
+    assert!(!p.run_to_input());
+    assert_eq!(p.get_output(), Some(5));
+}
+
+#[test]
+fn test_soft_reset_keeps_memory() {
+    // Adds the input to address 100, outputs it and adjusts the relative base.
+    let tape = vec![3, 11, 1, 100, 11, 100, 4, 100, 109, 7, 99, 0];
+    let mut soft = Program::new(tape);
+    soft.push_input(5);
+    soft.run_till_halted_or_blocked().unwrap();
+    assert_eq!(soft.get_output(), Some(5));
+
+    let mut full = soft.fork();
+    soft.soft_reset();
+    full.reset();
+    for program in [&soft, &full] {
+        assert_eq!(program.sp, 0);
+        assert_eq!(program.relative_base, 0);
+        assert_eq!(program.state(), State::Running);
+        assert!(!program.has_output());
+    }
+    assert_eq!(soft.get_mem(100), 5);
+    assert_eq!(full.get_mem(100), 0);
+
+    soft.push_input(3);
+    soft.run_till_halted_or_blocked().unwrap();
+    assert_eq!(soft.get_output(), Some(8));
+    full.push_input(3);
+    full.run_till_halted_or_blocked().unwrap();
+    assert_eq!(full.get_output(), Some(3));
+}
+
+#[test]
+fn test_run_fast_matches_try_run() {
+    // Reads inputs into consecutive addresses from 100 on, and outputs each
+    // one doubled, until it reads a 0.
+    let tape = vec![109, 1, 203, 99, 1206, 99, 16, 1202, 99, 2, 50, 4, 50, 1105, 1, 0, 99];
+    let mut general = Program::new(&tape[..]);
+    let mut fast = Program::new(tape);
+    for program in [&mut general, &mut fast] {
+        program.push_input(3);
+        program.push_input(4);
+    }
+    general.try_run().unwrap();
+    fast.run_fast().unwrap();
+    assert_eq!(fast.state(), State::WaitingForInput);
+    assert_eq!(fast.sp, general.sp);
+    assert_eq!(fast.output, vec![6, 8]);
+    assert_eq!(fast.output, general.output);
+
+    for program in [&mut general, &mut fast] {
+        program.push_input(0);
+    }
+    general.try_run().unwrap();
+    fast.run_fast().unwrap();
+    assert!(fast.halted());
+    assert_eq!(fast.effective_memory(), general.effective_memory());
+    assert_eq!(fast.relative_base, general.relative_base);
+    assert_eq!(fast.counters.steps, general.counters.steps);
+}
+
+#[test]
+fn test_run_fast_checks_unused_modes() {
+    // Output only has one parameter, but its third mode digit is still bad.
+    let err = RunError::UnknownParameterMode { sp: 0, mode: 3 };
+    assert_eq!(Program::new(vec![30104, 7, 99]).try_run(), Err(err.clone()));
+    assert_eq!(Program::new(vec![30104, 7, 99]).run_fast(), Err(err));
+}
+
+#[test]
+fn test_error_handler_halts() {
+    use std::sync::{Arc, Mutex};
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    for tape in [vec![11101, 1, 1, 0, 99], vec![304, 0, 99], vec![3, 100, 99]] {
+        let mut p = Program::new(tape);
+        p.set_input_policy(InputPolicy::Error);
+        let sink = Arc::clone(&errors);
+        p.set_error_handler(move |e| sink.lock().unwrap().push(e.to_string()));
+        p.run_or_panic();
+        assert!(p.halted());
+        assert_eq!(p.sp, 0);
+    }
+    assert_eq!(*errors.lock().unwrap(), vec![
+        "write to a parameter in immediate mode at address 0",
+        "unknown parameter mode 3 at address 0",
+        "input exhausted",
+    ]);
+}
+
+#[test]
+fn test_conditional_breakpoint_resumes() {
+    // Counts up from 1 at address 100, outputting every number.
+    let mut p = Program::new(vec![101, 1, 100, 100, 4, 100, 1105, 1, 0]);
+    p.add_conditional_breakpoint(4, |p| p.get_mem(100) % 3 == 0);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(4));
+    assert_eq!(p.get_mem(100), 3);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(4));
+    assert_eq!(p.get_mem(100), 6);
+    assert_eq!(p.pending_output(), &[1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn test_breakpoint_on_input_resumes_after_waiting() {
+    let mut p = Program::new(vec![104, 1, 3, 100, 4, 100, 99]);
+    p.add_breakpoint(2);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(2));
+    assert_eq!(p.run_to_breakpoint(), BreakReason::WaitingForInput);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::WaitingForInput);
+    p.push_input(5);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Halted);
+    assert_eq!(p.pending_output(), &[1, 5]);
+
+    // Reads input forever, stopping at the input whenever address 100 is 0.
+    let mut p = Program::new(vec![3, 100, 1105, 1, 0]);
+    p.add_conditional_breakpoint(0, |p| p.get_mem(100) == 0);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(0));
+    assert_eq!(p.run_to_breakpoint(), BreakReason::WaitingForInput);
+    p.push_input(0);
+    assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(0));
+    assert_eq!(p.pending_input(), &[]);
+}
+
+#[test]
+fn test_breakpoint_stops_once_per_visit() {
+    // Counts up from 1 at address 100, outputting every number.
+    let mut p = Program::new(vec![101, 1, 100, 100, 4, 100, 1105, 1, 0]);
+    p.add_breakpoint(4);
+    for n in 1..=3 {
+        assert_eq!(p.run_to_breakpoint(), BreakReason::Breakpoint(4));
+        assert_eq!(p.sp, 4);
+        assert_eq!(p.get_mem(100), n);
+    }
+    assert_eq!(p.pending_output(), &[1, 2]);
+}
+
+#[test]
+#[should_panic(expected = "output 1 is 2, expected 3\noutput 2 is missing, expected 4")]
+fn test_assert_outputs_lists_differences() {
+    Program::new(vec![104, 1, 104, 2, 99]).assert_outputs(&[1, 3, 4]);
+}
+
+#[test]
+fn test_invalid_addresses() {
+    let mut p = Program::new(vec![4, -3, 99]);
+    assert_eq!(p.try_run(), Err(RunError::NegativeAddress(-3)));
+    assert_eq!(p.sp, 0);
+
+    let mut p = Program::new(vec![109, Number::MAX, 204, 1, 99]);
+    assert_eq!(p.try_run(), Err(RunError::AddressOverflow));
+    assert_eq!(p.sp, 2);
+
+    let mut p = Program::new(vec![1105, 1, -1]);
+    assert_eq!(p.run_fast(), Err(RunError::NegativeAddress(-1)));
+
+    for fast in [false, true] {
+        let mut p = Program::new(vec![109, Number::MAX, 109, 1, 99]);
+        let result = if fast { p.run_fast() } else { p.try_run() };
+        assert_eq!(result, Err(RunError::AddressOverflow));
+        assert_eq!(p.sp, 2);
+        assert_eq!(p.relative_base, Number::MAX);
+
+        let mut p = Program::new(vec![3, -5, 99]);
+        p.push_input(42);
+        let result = if fast { p.run_fast() } else { p.try_run() };
+        assert_eq!(result, Err(RunError::NegativeAddress(-5)));
+        assert_eq!(p.sp, 0);
+        assert_eq!(p.pending_input(), &[42]);
+    }
+}
+
+#[test]
+fn test_try_run_invalid_instructions() {
+    assert_eq!(
+        Program::new(vec![104, 1, 42, 99]).try_run(),
+        Err(RunError::UnknownOpcode { sp: 2, opcode: 42 })
+    );
+    assert_eq!(
+        Program::new(vec![1105, 1, 3, 304, 0, 99]).try_run(),
+        Err(RunError::UnknownParameterMode { sp: 3, mode: 3 })
+    );
+    let mut p = Program::new(vec![11101, 1, 1, 0, 99]);
+    assert_eq!(p.try_run(), Err(RunError::WriteToImmediate { sp: 0 }));
+    assert_eq!(p.run_fast(), Err(RunError::WriteToImmediate { sp: 0 }));
+    assert_eq!(p.state(), State::Running);
+
+    for tape in [vec![104, 0, 1101, Number::MAX, 1, 0, 99], vec![104, 0, 1102, Number::MIN, -1, 0, 99]] {
+        let mut p = Program::new(tape.clone());
+        assert_eq!(p.try_run(), Err(RunError::ArithmeticOverflow { sp: 2 }));
+        let mut fast = Program::new(tape);
+        assert_eq!(fast.run_fast(), Err(RunError::ArithmeticOverflow { sp: 2 }));
+        assert_eq!(fast.sp, 2);
+        assert_eq!(fast.get_mem(0), 104);
+    }
+}
+
+#[test]
+fn test_write_mem_beyond_tape() {
+    let mut p = Program::new(vec![4, 10, 99]);
+    p.strict_reads(true);
+    p.write_mem(10, -3);
+    assert_eq!(p.try_run(), Ok(()));
+    assert_eq!(p.get_output(), Some(-3));
+    assert_eq!(p.memory_snapshot().len(), 11);
+    assert_eq!(p.distinct_writes(), 0);
+}
+
+#[test]
+fn test_reset_for_repeated_runs() {
+    // Adds the noun and the verb into address 0, also writes it beyond the
+    // tape at 20, and outputs it.
+    let tape = vec![1, 0, 0, 0, 1, 0, 0, 20, 4, 20, 99];
+    let mut p = Program::new(&tape[..]);
+    let mut results = Vec::new();
+    for (noun, verb) in [(0, 0), (9, 10), (1, 2)] {
+        p.reset();
+        p.write_mem(1, noun);
+        p.write_mem(2, verb);
+        p.run_till_halted_or_blocked().unwrap();
+        results.push(p.result());
+
+        let mut fresh = Program::new(&tape[..]);
+        fresh.write_mem(1, noun);
+        fresh.write_mem(2, verb);
+        fresh.run_till_halted_or_blocked().unwrap();
+        assert_eq!(p.to_json(), fresh.to_json());
+    }
+    assert_eq!(results, vec![2, 119, 3]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_state_implements_serde() {
+    fn check<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+    check::<State>();
+}
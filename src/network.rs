@@ -0,0 +1,199 @@
+use std::ops::ControlFlow;
+
+use crate::{Number, Program};
+
+/// The address of the NAT in a `Network`. Packets sent to it are kept back
+/// until the network goes idle.
+pub const NAT_ADDRESS: Number = 255;
+
+/// A packet sent from one machine in a `Network` to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    /// The address of the machine that sent the packet, or `NAT_ADDRESS`.
+    pub from: Number,
+    /// The address the packet was sent to.
+    pub to: Number,
+    pub x: Number,
+    pub y: Number,
+}
+
+/// A network of machines that send each other packets, like the one in day
+/// 23.
+///
+/// Every machine is given its address as its first input. A machine sends a
+/// packet by outputting the address it is for followed by the packet's x and
+/// y, which are then added to that machine's input queue. A machine that
+/// wants input while it has none reads -1.
+///
+/// # Example
+/// ```
+/// use intcode::{Network, Packet};
+///
+/// // Reads its address, then sends (address + 1, 5, 6) to the machine at
+/// // address + 1, and from then on reads input forever.
+/// let tape = vec![3, 100, 101, 1, 100, 101, 4, 101, 104, 5, 104, 6, 3, 102, 1105, 1, 12];
+/// let mut network = Network::new(&tape, 2);
+/// network.step();
+/// assert_eq!(network.delivered(), &[
+///     Packet { from: 0, to: 1, x: 5, y: 6 },
+///     Packet { from: 1, to: 2, x: 5, y: 6 },
+/// ]);
+/// ```
+pub struct Network {
+    machines: Vec<Program>,
+    delivered: Vec<Packet>,
+    nat: Option<Packet>,
+    idle: bool,
+}
+
+impl Network {
+    /// Creates a network of `size` machines that all run the given tape, at
+    /// addresses 0 up to `size`.
+    pub fn new(tape: &[Number], size: usize) -> Network {
+        let machines = (0..size)
+            .map(|addr| {
+                let mut machine = Program::new(tape);
+                machine.push_input(addr as Number);
+                machine
+            })
+            .collect();
+        Network {
+            machines,
+            delivered: Vec::new(),
+            nat: None,
+            idle: false,
+        }
+    }
+
+    /// Runs every machine in turn until it needs input that isn't there, and
+    /// delivers the packets it sends. A machine that has no input at the start
+    /// of its turn is given -1 first. Returns the packets sent during this
+    /// step.
+    ///
+    /// Packets for addresses that don't belong to any machine are not
+    /// delivered anywhere, but they are returned, and included in
+    /// `delivered`. The last one sent to `NAT_ADDRESS` is kept by the NAT.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the machines fails, under the same conditions as
+    /// `Program::run_or_panic`.
+    pub fn step(&mut self) -> Vec<Packet> {
+        let mut sent = Vec::new();
+        let mut starved = true;
+        for addr in 0..self.machines.len() {
+            let machine = &mut self.machines[addr];
+            if machine.halted() {
+                continue;
+            }
+            if machine.pending_input().is_empty() {
+                machine.push_input(-1);
+            } else {
+                starved = false;
+            }
+            machine.run_without_pausing();
+            for record in machine.drain_output_chunks(3) {
+                let packet = Packet { from: addr as Number, to: record[0], x: record[1], y: record[2] };
+                self.deliver(packet);
+                sent.push(packet);
+            }
+        }
+        self.idle = starved && sent.is_empty();
+        self.delivered.extend_from_slice(&sent);
+        sent
+    }
+
+    fn deliver(&mut self, packet: Packet) {
+        if packet.to == NAT_ADDRESS {
+            self.nat = Some(packet);
+        } else if let Some(machine) = usize::try_from(packet.to).ok().and_then(|to| self.machines.get_mut(to)) {
+            machine.push_input(packet.x);
+            machine.push_input(packet.y);
+        }
+    }
+
+    /// Keeps running steps, and whenever the network is idle, has the NAT send
+    /// the last packet it received to the machine at address 0 and calls `f`
+    /// with that packet. If `f` returns `ControlFlow::Break`, this stops right
+    /// there.
+    ///
+    /// Returns `ControlFlow::Break` if `f` stopped the network, and
+    /// `ControlFlow::Continue` if all machines halted. If neither ever happens,
+    /// this method never returns.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `step`.
+    ///
+    /// # Example
+    /// ```
+    /// use std::ops::ControlFlow;
+    ///
+    /// // Reads its address and sends (255, address, 7) to the NAT. Then it
+    /// // reads input forever.
+    /// let tape = vec![3, 100, 104, 255, 4, 100, 104, 7, 3, 101, 1105, 1, 8];
+    /// let mut network = intcode::Network::new(&tape, 3);
+    ///
+    /// let mut woken = Vec::new();
+    /// network.run_with_nat(|packet| {
+    ///     woken.push((packet.to, packet.x, packet.y));
+    ///     ControlFlow::Break(())
+    /// });
+    /// assert_eq!(woken, vec![(0, 2, 7)]);
+    /// ```
+    pub fn run_with_nat<F: FnMut(&Packet) -> ControlFlow<()>>(&mut self, mut f: F) -> ControlFlow<()> {
+        loop {
+            if self.machines.iter().all(Program::halted) {
+                return ControlFlow::Continue(());
+            }
+            self.step();
+            if !self.idle {
+                continue;
+            }
+            if let Some(Packet { x, y, .. }) = self.nat {
+                let packet = Packet { from: NAT_ADDRESS, to: 0, x, y };
+                self.deliver(packet);
+                self.delivered.push(packet);
+                f(&packet)?;
+            }
+        }
+    }
+
+    /// Returns `true` if during the last step, no machine had any input to
+    /// read and none of them sent a packet.
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Returns the last packet sent to the NAT, if any.
+    pub fn nat_packet(&self) -> Option<Packet> {
+        self.nat
+    }
+
+    /// Returns every packet sent so far, including the ones sent by the NAT,
+    /// in the order they were sent.
+    pub fn delivered(&self) -> &[Packet] {
+        &self.delivered
+    }
+
+    /// Returns the machines in the network, in order of address.
+    pub fn machines(&self) -> &[Program] {
+        &self.machines
+    }
+}
+
+#[test]
+fn test_network_goes_idle() {
+    // Reads its address, and if it is 0 sends (0, 3, 4) to itself. Then it
+    // reads input forever.
+    let tape = vec![3, 100, 1005, 100, 11, 104, 0, 104, 3, 104, 4, 3, 101, 1105, 1, 11];
+    let mut network = Network::new(&tape, 2);
+    assert_eq!(network.step().len(), 1);
+    assert_eq!(network.machines()[0].pending_input(), &[3, 4]);
+    assert!(!network.is_idle());
+    assert!(network.step().is_empty());
+    assert!(!network.is_idle());
+    assert!(network.step().is_empty());
+    assert!(network.is_idle());
+    assert_eq!(network.nat_packet(), None);
+}
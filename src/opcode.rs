@@ -0,0 +1,39 @@
+//! The numbers that encode each opcode.
+//!
+//! An instruction's opcode is its value modulo 100. The digits above that give
+//! the parameter modes, so these constants can be used as they are for
+//! instructions that only take parameters in position mode.
+//!
+//! # Example
+//! ```
+//! use intcode::{opcode, Program};
+//!
+//! let mut program = Program::new(vec![opcode::OUTPUT + 100, 7, opcode::HALT]);
+//! program.run_till_halted_or_blocked().unwrap();
+//! assert_eq!(program.get_output(), Some(7));
+//! ```
+
+use crate::Number;
+
+/// Adds the first two parameters and stores the result in the third.
+pub const ADD: Number = 1;
+/// Multiplies the first two parameters and stores the result in the third.
+pub const MULTIPLY: Number = 2;
+/// Reads an input value and stores it in the parameter.
+pub const INPUT: Number = 3;
+/// Outputs the value of the parameter.
+pub const OUTPUT: Number = 4;
+/// Jumps to the second parameter if the first is not zero.
+pub const JUMP_IF_TRUE: Number = 5;
+/// Jumps to the second parameter if the first is zero.
+pub const JUMP_IF_FALSE: Number = 6;
+/// Stores 1 in the third parameter if the first is less than the second, and
+/// 0 otherwise.
+pub const LESS_THAN: Number = 7;
+/// Stores 1 in the third parameter if the first two are equal, and 0
+/// otherwise.
+pub const EQUALS: Number = 8;
+/// Adds the parameter to the relative base.
+pub const RELATIVE_BASE_OFFSET: Number = 9;
+/// Stops the program.
+pub const HALT: Number = 99;
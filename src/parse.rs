@@ -0,0 +1,339 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::{Number, Program};
+
+/// An error encountered while reading an Intcode program. All of the ways to
+/// read a program return this.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The input contains nothing but whitespace and comments.
+    Empty,
+    /// A token could not be parsed as a `Number`. The index is the position of
+    /// the token in the comma-separated list, starting at 0, and the line is
+    /// the line the token starts on, starting at 1.
+    InvalidToken {
+        index: usize,
+        line: usize,
+        text: String,
+    },
+    /// A byte buffer's length is not a multiple of 8, so it can't hold whole
+    /// little-endian `Number`s.
+    InvalidByteLength(usize),
+    /// Text passed to `Program::from_json` isn't valid JSON, or doesn't
+    /// describe a program.
+    InvalidJson(String),
+    /// Reading the program failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "program is empty"),
+            ParseError::InvalidToken { index, line, text } => {
+                write!(f, "invalid token {:?} at index {} on line {}", text, index, line)
+            }
+            ParseError::InvalidByteLength(len) => {
+                write!(f, "byte length {} is not a multiple of 8", len)
+            }
+            ParseError::InvalidJson(message) => write!(f, "invalid JSON: {}", message),
+            ParseError::Io(e) => write!(f, "could not read program: {}", e),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Program, ParseError> {
+        Program::parse(s)
+    }
+}
+
+/// Parses a single token, ignoring any whitespace around it. The line passed in
+/// is the line the token's leading whitespace starts on.
+fn parse_token(index: usize, line: usize, token: &[u8]) -> Result<Number, ParseError> {
+    let start = token.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(token.len());
+    let line = line + token[..start].iter().filter(|&&b| b == b'\n').count();
+    let text = String::from_utf8_lossy(token);
+    let text = text.trim();
+    text.parse().map_err(|_| ParseError::InvalidToken {
+        index,
+        line,
+        text: text.to_string(),
+    })
+}
+
+impl Program {
+    /// Reads a comma-separated Intcode program from a reader.
+    ///
+    /// The program is read one token at a time, so it never needs to be in
+    /// memory as a whole string. Whitespace around tokens is ignored, as is a
+    /// single empty token at the very end, so a trailing comma or newline is
+    /// fine.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::Empty` if there are no tokens at all,
+    /// `ParseError::InvalidToken` if a token isn't a valid `Number`, and
+    /// `ParseError::Io` if reading fails.
+    ///
+    /// # Example
+    /// ```
+    /// let input = "104,1125899906842624,99\n";
+    /// let mut program = intcode::Program::from_reader(input.as_bytes()).unwrap();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(1125899906842624));
+    /// ```
+    pub fn from_reader<R: BufRead>(mut r: R) -> Result<Program, ParseError> {
+        let mut tape = Vec::new();
+        let mut token = Vec::new();
+        let mut line = 1;
+
+        loop {
+            token.clear();
+            if r.read_until(b',', &mut token)? == 0 {
+                break;
+            }
+            let last = token.last() != Some(&b',');
+            if last {
+                if token.iter().all(u8::is_ascii_whitespace) {
+                    break;
+                }
+            } else {
+                token.pop();
+            }
+            tape.push(parse_token(tape.len(), line, &token)?);
+            line += token.iter().filter(|&&b| b == b'\n').count();
+            if last {
+                break;
+            }
+        }
+
+        if tape.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        Ok(Program::new(tape))
+    }
+
+    /// Parses a comma-separated Intcode program, such as a puzzle input.
+    ///
+    /// Whitespace around tokens is ignored, as is a single empty token at the
+    /// very end, so a trailing comma or newline is fine. This is the same as
+    /// `from_reader`, and the same as parsing with `str::parse`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::Empty` if there are no tokens at all, and
+    /// `ParseError::InvalidToken`, which says where the token is, if a token
+    /// isn't a valid `Number`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::Program;
+    ///
+    /// let mut program = Program::parse("1,9,10,3,2,3,11,0,99,30,40,50\n").unwrap();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.result(), 3500);
+    ///
+    /// let program: Program = "104,7,99".parse().unwrap();
+    /// assert_eq!(program.initial_tape_string(), "104,7,99");
+    /// ```
+    pub fn parse(source: &str) -> Result<Program, ParseError> {
+        Program::from_reader(source.as_bytes())
+    }
+
+    /// Parses a comma-separated Intcode program that may contain comments.
+    ///
+    /// Everything from a `#` up to the end of its line is ignored, and so is
+    /// any whitespace, including newlines, around the tokens. This makes it
+    /// possible to write readable test programs by hand. Line numbers in
+    /// errors refer to the original text, comments included.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::Empty` if there are no tokens at all, and
+    /// `ParseError::InvalidToken` if a token isn't a valid `Number`.
+    ///
+    /// # Example
+    /// ```
+    /// let source = "
+    ///     3, 5,   # read into address 5
+    ///     4, 5,   # write it back out
+    ///     99,
+    ///     0,
+    /// ";
+    /// let mut program = intcode::Program::parse_annotated(source).unwrap();
+    /// program.push_input(9);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(9));
+    /// ```
+    pub fn parse_annotated(source: &str) -> Result<Program, ParseError> {
+        let stripped = source
+            .lines()
+            .map(|line| line.split('#').next().unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Program::from_reader(stripped.as_bytes())
+    }
+
+    /// Returns the program's initial tape in the format of the puzzle input:
+    /// numbers separated by commas, without spaces or a trailing newline.
+    ///
+    /// This is the tape the program was created with, or last given with
+    /// `reload_code`, which is also what `reset` restores. Changes the program
+    /// made to its memory while running are not included.
+    ///
+    /// # Example
+    /// ```
+    /// let mut program = intcode::Program::from_reader("1,0,0,0,99\n".as_bytes()).unwrap();
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.result(), 2);
+    /// assert_eq!(program.initial_tape_string(), "1,0,0,0,99");
+    /// ```
+    pub fn initial_tape_string(&self) -> String {
+        self.initial
+            .iter()
+            .map(|val| val.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Serializes the program's memory to bytes.
+    ///
+    /// Memory is written from address 0 up to and including the highest
+    /// address that holds a value, and any gaps are written as 0. Each value
+    /// takes up 8 bytes, in little-endian order, so the value at address `n`
+    /// is found at bytes `8 * n` up to `8 * n + 8`. Nothing else is written:
+    /// the stack pointer, the relative base and the queues are not included.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![1, -1]);
+    /// let bytes = program.memory_to_bytes();
+    /// assert_eq!(bytes.len(), 16);
+    /// assert_eq!(bytes[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+    /// assert_eq!(bytes[8..], [255; 8]);
+    /// ```
+    pub fn memory_to_bytes(&self) -> Vec<u8> {
+        self.effective_memory()
+            .iter()
+            .flat_map(|val| val.to_le_bytes())
+            .collect()
+    }
+
+    /// Creates a new Intcode program from bytes in the format written by
+    /// `memory_to_bytes`. The stack pointer and the relative base start out
+    /// as 0, as they do for `Program::new`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::InvalidByteLength` if the number of bytes is not a
+    /// multiple of 8.
+    ///
+    /// # Example
+    /// ```
+    /// let program = intcode::Program::new(vec![104, 7, 99]);
+    /// let bytes = program.memory_to_bytes();
+    ///
+    /// let mut copy = intcode::Program::from_bytes(&bytes).unwrap();
+    /// copy.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(copy.get_output(), Some(7));
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Program, ParseError> {
+        Ok(Program::new(Program::memory_from_bytes(bytes)?))
+    }
+
+    /// Reads a tape from bytes in the format written by `memory_to_bytes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ParseError::InvalidByteLength` if the number of bytes is not a
+    /// multiple of 8.
+    ///
+    /// # Example
+    /// ```
+    /// let bytes = [2, 0, 0, 0, 0, 0, 0, 0, 254, 255, 255, 255, 255, 255, 255, 255];
+    /// let tape = intcode::Program::memory_from_bytes(&bytes).unwrap();
+    /// assert_eq!(tape, vec![2, -2]);
+    /// ```
+    pub fn memory_from_bytes(bytes: &[u8]) -> Result<Vec<Number>, ParseError> {
+        if !bytes.len().is_multiple_of(8) {
+            return Err(ParseError::InvalidByteLength(bytes.len()));
+        }
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| Number::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+}
+
+#[test]
+fn test_from_reader_split_buffers() {
+    let input = "1001,-22,\n 33 ,4";
+    let reader = io::BufReader::with_capacity(3, input.as_bytes());
+    let p = Program::from_reader(reader).unwrap();
+    assert_eq!(*p.program, vec![1001, -22, 33, 4]);
+}
+
+#[test]
+fn test_from_reader_invalid_token() {
+    match Program::from_reader("1,2,x3,4".as_bytes()) {
+        Err(ParseError::InvalidToken { index, line, text }) => {
+            assert_eq!(index, 2);
+            assert_eq!(line, 1);
+            assert_eq!(text, "x3");
+        }
+        _ => panic!("expected an invalid token error"),
+    }
+}
+
+#[test]
+fn test_bytes_round_trip_extra_memory() {
+    let mut p = Program::new(vec![1, 2, 3]);
+    p.set_mem(5, -7);
+    let copy = Program::from_bytes(&p.memory_to_bytes()).unwrap();
+    assert_eq!(*copy.program, vec![1, 2, 3, 0, 0, -7]);
+    assert!(matches!(
+        Program::from_bytes(&[0; 9]),
+        Err(ParseError::InvalidByteLength(9))
+    ));
+}
+
+#[test]
+fn test_parse_annotated_error_position() {
+    let source = "1, 2, # a comment, with a comma\n\n  3, oops, 5\n";
+    match Program::parse_annotated(source) {
+        Err(ParseError::InvalidToken { index, line, text }) => {
+            assert_eq!(index, 3);
+            assert_eq!(line, 3);
+            assert_eq!(text, "oops");
+        }
+        _ => panic!("expected an invalid token error"),
+    }
+}
+
+#[test]
+fn test_empty_input() {
+    assert!(matches!(Program::from_reader(" \n".as_bytes()), Err(ParseError::Empty)));
+    assert!(matches!(Program::parse_annotated("# nothing here\n"), Err(ParseError::Empty)));
+    assert_eq!(ParseError::Empty.to_string(), "program is empty");
+}
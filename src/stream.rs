@@ -0,0 +1,192 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{InputReader, Number, OutputWriter, Program, RunError};
+
+/// How `Program::set_input_reader` turns lines of text into input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// Send each line as ASCII, one byte at a time, followed by a newline.
+    Ascii,
+    /// Send each line as a single number in decimal. Whitespace around the
+    /// number is ignored, and so are empty lines.
+    Decimal,
+}
+
+/// How `Program::set_output_writer` writes outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Write each output as a single byte, for programs that talk in ASCII.
+    /// Outputs that don't fit in a byte, which is usually how such programs
+    /// give their answer, are written in decimal on a line of their own.
+    Ascii,
+    /// Write each output in decimal, followed by a newline.
+    Decimal,
+}
+
+impl OutputWriter {
+    pub(crate) fn write(&mut self, value: Number) -> io::Result<()> {
+        match (self.mode, u8::try_from(value)) {
+            (OutputMode::Ascii, Ok(b)) => {
+                self.writer.write_all(&[b])?;
+                self.at_line_start = b == b'\n';
+            }
+            (OutputMode::Ascii, Err(_)) if !self.at_line_start => {
+                writeln!(self.writer, "\n{}", value)?;
+                self.at_line_start = true;
+            }
+            _ => writeln!(self.writer, "{}", value)?,
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Reads lines from the input reader until one of them gives input, and
+    /// queues that input. At the end of the reader, nothing is queued.
+    pub(crate) fn read_input_line(&mut self) -> Result<(), RunError> {
+        let Some(reader) = self.input_reader.as_mut() else {
+            return Ok(());
+        };
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.reader.read_line(&mut line).map_err(|e| RunError::InputFailed(e.kind()))? == 0 {
+                return Ok(());
+            }
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            match reader.mode {
+                InputMode::Ascii => {
+                    let line = line.strip_suffix('\r').unwrap_or(line);
+                    self.input.extend(line.bytes().map(Number::from));
+                    self.input.push(10);
+                    return Ok(());
+                }
+                InputMode::Decimal if line.trim().is_empty() => continue,
+                InputMode::Decimal => {
+                    let value = line
+                        .trim()
+                        .parse()
+                        .map_err(|_| RunError::InputFailed(io::ErrorKind::InvalidData))?;
+                    self.input.push(value);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Sets a reader for the program to read input from, such as a file or
+    /// standard input, whenever its input queue is empty. Any reader set
+    /// earlier is replaced.
+    ///
+    /// One line is read at a time, and turned into input according to `mode`.
+    /// Input pushed with `push_input` is still read first. Once the reader has
+    /// nothing more to read, the program does what the input policy says, so
+    /// by default it waits for input, and tries the reader again when it runs
+    /// again.
+    ///
+    /// If reading fails, the input instruction fails with
+    /// `RunError::InputFailed`, which `try_run` returns, and the other ways of
+    /// running the program panic with. A line that isn't a number in
+    /// `InputMode::Decimal` fails the same way, with
+    /// `io::ErrorKind::InvalidData`.
+    ///
+    /// # Example
+    /// ```
+    /// use intcode::InputMode;
+    ///
+    /// // Adds up two inputs.
+    /// let mut program = intcode::Program::new(vec![3, 100, 3, 101, 1, 100, 101, 100, 4, 100, 99]);
+    /// program.set_input_reader("20\n\n  22\n".as_bytes(), InputMode::Decimal);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(program.get_output(), Some(42));
+    /// ```
+    pub fn set_input_reader<R: BufRead + Send + 'static>(&mut self, r: R, mode: InputMode) {
+        self.input_reader = Some(InputReader {
+            reader: Box::new(r),
+            mode,
+        });
+    }
+
+    /// Sets a writer that every output is written to as the program produces
+    /// it, such as a file or standard output. Any writer set earlier is
+    /// replaced.
+    ///
+    /// Outputs are still queued as well, unless that is turned off with
+    /// `set_queue_output`. Nothing is flushed by the program, so a buffered
+    /// writer only writes everything out once it is dropped along with the
+    /// program, or when its buffer fills up.
+    ///
+    /// If writing fails, the output instruction fails with
+    /// `RunError::OutputFailed`, which `try_run` returns, and the other ways of
+    /// running the program panic with. The output isn't queued then.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use intcode::OutputMode;
+    ///
+    /// #[derive(Clone, Default)]
+    /// struct Shared(Arc<Mutex<Vec<u8>>>);
+    ///
+    /// impl Write for Shared {
+    ///     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> std::io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let text = Shared::default();
+    /// let mut program = intcode::Program::new(vec![104, 72, 104, 105, 104, 1000, 99]);
+    /// program.set_output_writer(text.clone(), OutputMode::Ascii);
+    /// program.run_till_halted_or_blocked().unwrap();
+    /// assert_eq!(*text.0.lock().unwrap(), b"Hi\n1000\n");
+    /// assert_eq!(program.get_output(), Some(72));
+    /// ```
+    pub fn set_output_writer<W: Write + Send + 'static>(&mut self, w: W, mode: OutputMode) {
+        self.output_writer = Some(OutputWriter {
+            writer: Box::new(w),
+            mode,
+            at_line_start: true,
+        });
+    }
+}
+
+#[test]
+fn test_output_writer_error() {
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::WriteZero.into())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut program = Program::new(vec![104, 1, 99]);
+    program.set_output_writer(Full, OutputMode::Decimal);
+    assert_eq!(program.try_run(), Err(crate::RunError::OutputFailed(io::ErrorKind::WriteZero)));
+    assert_eq!(program.sp, 0);
+    assert_eq!(program.get_output(), None);
+}
+
+#[test]
+fn test_input_reader_modes() {
+    // Outputs every input it reads.
+    let tape = vec![3, 100, 4, 100, 1105, 1, 0];
+    let mut program = Program::new(&tape[..]);
+    program.push_input(7);
+    program.set_input_reader("ab\r\n\nc".as_bytes(), InputMode::Ascii);
+    program.run_till_halted_or_blocked().unwrap();
+    assert_eq!(program.pending_output(), &[7, 97, 98, 10, 10, 99, 10]);
+
+    let mut program = Program::new(tape);
+    program.set_input_reader("1\nx\n".as_bytes(), InputMode::Decimal);
+    assert_eq!(program.try_run(), Err(RunError::InputFailed(io::ErrorKind::InvalidData)));
+    assert_eq!(program.pending_output(), &[1]);
+}
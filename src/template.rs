@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use crate::{ExtraMemory, InputPolicy, Number, Program};
+
+/// A frozen program that fresh copies can be made of cheaply, for searches
+/// that run the same program many times with different input. Get one from
+/// `Program::template`.
+///
+/// Every instance shares the template's tape until it writes to it, so
+/// making one costs about as much as making a fork.
+///
+/// # Example
+/// ```
+/// // Outputs its input times 3.
+/// let program = intcode::Program::new(vec![3, 7, 1002, 7, 3, 7, 104, 0, 4, 7, 99]);
+/// let template = program.template();
+///
+/// let tripled: Vec<_> = (1..=3)
+///     .map(|i| {
+///         let mut instance = template.instance();
+///         instance.push_input(i);
+///         instance.run_till_halted_or_blocked().unwrap();
+///         instance.last_output().unwrap()
+///     })
+///     .collect();
+/// assert_eq!(tripled, vec![3, 6, 9]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Template {
+    tape: Arc<Vec<Number>>,
+    extra: ExtraMemory,
+    input_policy: InputPolicy,
+    strict_reads: bool,
+    queue_output: bool,
+    pause_on_output: bool,
+}
+
+impl Template {
+    /// Creates a new program from the template, ready to run from the start.
+    pub fn instance(&self) -> Program {
+        let mut program = Program::from_tape(Arc::clone(&self.tape));
+        program.extra = Arc::new(self.extra.clone());
+        program.input_policy = self.input_policy;
+        program.strict_reads = self.strict_reads;
+        program.queue_output = self.queue_output;
+        program.pause_on_output = self.pause_on_output;
+        program
+    }
+}
+
+impl Program {
+    /// Turns the program into a `Template` that new instances of it can be
+    /// made from.
+    ///
+    /// The template holds the initial tape, the one `reset` restores, along
+    /// with the memory set aside with `ProgramBuilder::memory_hint` and
+    /// settings such as the input policy and strict reads. Everything that
+    /// `reset` throws away is left out, and so are functions such as the ones
+    /// set with `on_output`, breakpoints and watchpoints.
+    pub fn template(self) -> Template {
+        let mut extra = Arc::unwrap_or_clone(self.extra);
+        extra.clear();
+        Template {
+            tape: self.initial,
+            extra,
+            input_policy: self.input_policy,
+            strict_reads: self.strict_reads,
+            queue_output: self.queue_output,
+            pause_on_output: self.pause_on_output,
+        }
+    }
+}
+
+#[test]
+fn test_instances_are_independent() {
+    let mut program = Program::builder(vec![1101, 1, 2, 0, 1101, 3, 4, 20, 99]).memory_hint(30).build();
+    program.set_input_policy(InputPolicy::Error);
+    program.run_till_halted_or_blocked().unwrap();
+    let template = program.template();
+
+    let mut first = template.instance();
+    let second = template.instance();
+    assert!(Arc::ptr_eq(&first.program, &second.program));
+    assert_eq!(first.input_policy, InputPolicy::Error);
+    first.run_till_halted_or_blocked().unwrap();
+    assert_eq!(first.result(), 3);
+    assert_eq!(first.get_mem(20), 7);
+    assert_eq!(second.result(), 1101);
+    assert_eq!(second.get_mem(20), 0);
+    assert_eq!(second.extra.cells.len(), 21);
+}